@@ -0,0 +1,40 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+
+#[path = "../../src/modules/format.rs"]
+#[allow(dead_code)]
+mod format;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        let spans = format::parse(format::Style::default(), s);
+        // No formatting codes may end up in the text
+        let text = spans.iter().map(|s| s.text).collect::<String>();
+        let reparsed = format::parse(format::Style::default(), &text);
+        assert_eq!(text, reparsed.iter().map(|s| s.text).collect::<String>());
+
+        // Reopening the style at the end must fit into the reserved space, and reproduce it
+        let end = format::end_style(format::Style::default(), s);
+        let codes = end.codes();
+        assert!(codes.len() <= format::MAX_CODES_BYTES);
+        assert_eq!(format::end_style(format::Style::default(), &codes), end);
+    }
+});
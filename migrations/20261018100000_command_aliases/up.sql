@@ -0,0 +1,7 @@
+CREATE TABLE command_aliases (
+  server TEXT NOT NULL,
+  scope TEXT NOT NULL,
+  name TEXT NOT NULL,
+  expansion TEXT NOT NULL,
+  PRIMARY KEY (server, scope, name)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE user_preferences (
+  server TEXT NOT NULL,
+  nick TEXT NOT NULL,
+  name TEXT NOT NULL,
+  value TEXT NOT NULL,
+  PRIMARY KEY (server, nick, name)
+);
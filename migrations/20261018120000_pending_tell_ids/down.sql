@@ -0,0 +1,13 @@
+CREATE TABLE pending_tells_old (
+  date TEXT NOT NULL,
+  server_addr TEXT NOT NULL,
+  channel TEXT,
+  source_nick TEXT NOT NULL,
+  target_nick TEXT NOT NULL,
+  message TEXT NOT NULL,
+  PRIMARY KEY (date, message)
+);
+INSERT OR IGNORE INTO pending_tells_old
+  SELECT date, server_addr, channel, source_nick, target_nick, message FROM pending_tells;
+DROP TABLE pending_tells;
+ALTER TABLE pending_tells_old RENAME TO pending_tells;
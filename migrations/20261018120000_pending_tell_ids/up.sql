@@ -0,0 +1,14 @@
+CREATE TABLE pending_tells_new (
+  id INTEGER PRIMARY KEY NOT NULL,
+  date TEXT NOT NULL,
+  server_addr TEXT NOT NULL,
+  channel TEXT,
+  source_nick TEXT NOT NULL,
+  target_nick TEXT NOT NULL,
+  message TEXT NOT NULL,
+  snoozed_until TIMESTAMP
+);
+INSERT INTO pending_tells_new (date, server_addr, channel, source_nick, target_nick, message)
+  SELECT date, server_addr, channel, source_nick, target_nick, message FROM pending_tells;
+DROP TABLE pending_tells;
+ALTER TABLE pending_tells_new RENAME TO pending_tells;
@@ -0,0 +1 @@
+ALTER TABLE pending_tells ADD COLUMN expires_at TIMESTAMP;
@@ -0,0 +1,17 @@
+CREATE TABLE pending_tells_old (
+  id INTEGER PRIMARY KEY NOT NULL,
+  date TEXT NOT NULL,
+  server_addr TEXT NOT NULL,
+  channel TEXT,
+  source_nick TEXT NOT NULL,
+  target_nick TEXT NOT NULL,
+  message TEXT NOT NULL,
+  snoozed_until TIMESTAMP,
+  expires_at TIMESTAMP
+);
+INSERT INTO pending_tells_old
+  SELECT id, date, server_addr, channel, source_nick, target_nick, message, snoozed_until,
+    expires_at
+  FROM pending_tells;
+DROP TABLE pending_tells;
+ALTER TABLE pending_tells_old RENAME TO pending_tells;
@@ -0,0 +1 @@
+ALTER TABLE pending_tells ADD COLUMN source_network TEXT;
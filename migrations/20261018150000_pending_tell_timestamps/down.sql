@@ -0,0 +1,41 @@
+CREATE TABLE pending_tells_old (
+  id INTEGER PRIMARY KEY NOT NULL,
+  date TEXT NOT NULL,
+  server_addr TEXT NOT NULL,
+  channel TEXT,
+  source_nick TEXT NOT NULL,
+  target_nick TEXT NOT NULL,
+  message TEXT NOT NULL,
+  snoozed_until TIMESTAMP,
+  expires_at TIMESTAMP,
+  source_network TEXT
+);
+INSERT INTO pending_tells_old
+  SELECT id,
+    CASE strftime('%w', date)
+      WHEN '0' THEN 'Sun'
+      WHEN '1' THEN 'Mon'
+      WHEN '2' THEN 'Tue'
+      WHEN '3' THEN 'Wed'
+      WHEN '4' THEN 'Thu'
+      WHEN '5' THEN 'Fri'
+      WHEN '6' THEN 'Sat'
+    END || ', ' || strftime('%d', date) || ' ' || CASE strftime('%m', date)
+      WHEN '01' THEN 'Jan'
+      WHEN '02' THEN 'Feb'
+      WHEN '03' THEN 'Mar'
+      WHEN '04' THEN 'Apr'
+      WHEN '05' THEN 'May'
+      WHEN '06' THEN 'Jun'
+      WHEN '07' THEN 'Jul'
+      WHEN '08' THEN 'Aug'
+      WHEN '09' THEN 'Sep'
+      WHEN '10' THEN 'Oct'
+      WHEN '11' THEN 'Nov'
+      WHEN '12' THEN 'Dec'
+    END || ' ' || strftime('%Y %H:%M:%S', date),
+    server_addr, channel, source_nick, target_nick, message, snoozed_until, expires_at,
+    source_network
+  FROM pending_tells;
+DROP TABLE pending_tells;
+ALTER TABLE pending_tells_old RENAME TO pending_tells;
@@ -0,0 +1,35 @@
+-- Dates were stored as the first 25 chars of RFC 2822, e.g. `Sun, 18 Oct 2026 10:00:00`,
+-- chrono pads single-digit days with a space, e.g. `Thu,  1 Oct 2026 10:00:00`
+CREATE TABLE pending_tells_new (
+  id INTEGER PRIMARY KEY NOT NULL,
+  date TIMESTAMP NOT NULL,
+  server_addr TEXT NOT NULL,
+  channel TEXT,
+  source_nick TEXT NOT NULL,
+  target_nick TEXT NOT NULL,
+  message TEXT NOT NULL,
+  snoozed_until TIMESTAMP,
+  expires_at TIMESTAMP,
+  source_network TEXT
+);
+INSERT INTO pending_tells_new
+  SELECT id,
+    substr(date, 13, 4) || '-' || CASE substr(date, 9, 3)
+      WHEN 'Jan' THEN '01'
+      WHEN 'Feb' THEN '02'
+      WHEN 'Mar' THEN '03'
+      WHEN 'Apr' THEN '04'
+      WHEN 'May' THEN '05'
+      WHEN 'Jun' THEN '06'
+      WHEN 'Jul' THEN '07'
+      WHEN 'Aug' THEN '08'
+      WHEN 'Sep' THEN '09'
+      WHEN 'Oct' THEN '10'
+      WHEN 'Nov' THEN '11'
+      WHEN 'Dec' THEN '12'
+    END || '-' || replace(substr(date, 6, 2), ' ', '0') || ' ' || substr(date, 18, 8),
+    server_addr, channel, source_nick, target_nick, message, snoozed_until, expires_at,
+    source_network
+  FROM pending_tells;
+DROP TABLE pending_tells;
+ALTER TABLE pending_tells_new RENAME TO pending_tells;
@@ -0,0 +1,11 @@
+CREATE TABLE geocode_cache_old (
+  location TEXT NOT NULL PRIMARY KEY,
+  latitude REAL NOT NULL,
+  longitude REAL NOT NULL,
+  reverse_location TEXT NOT NULL
+);
+INSERT INTO geocode_cache_old
+  SELECT location, latitude, longitude, reverse_location
+  FROM geocode_cache;
+DROP TABLE geocode_cache;
+ALTER TABLE geocode_cache_old RENAME TO geocode_cache;
@@ -0,0 +1,2 @@
+-- Existing entries were all geocoded by MapQuest
+ALTER TABLE geocode_cache ADD COLUMN provider TEXT NOT NULL DEFAULT 'mapquest';
@@ -0,0 +1,2 @@
+DROP TABLE geoname_names;
+DROP TABLE geonames;
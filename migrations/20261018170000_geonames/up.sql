@@ -0,0 +1,14 @@
+CREATE TABLE geonames (
+  id INTEGER PRIMARY KEY NOT NULL,
+  name TEXT NOT NULL,
+  country_code TEXT NOT NULL,
+  latitude REAL NOT NULL,
+  longitude REAL NOT NULL,
+  population BIGINT NOT NULL
+);
+-- Lowercased names, ASCII names and alternate names of each place
+CREATE TABLE geoname_names (
+  name TEXT NOT NULL,
+  geoname_id INTEGER NOT NULL REFERENCES geonames (id) ON DELETE CASCADE,
+  PRIMARY KEY (name, geoname_id)
+);
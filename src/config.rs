@@ -33,6 +33,7 @@ pub struct Config {
 #[serde(deny_unknown_fields)]
 pub struct ServerCfg {
     pub address: String,
+    pub network: Option<String>,
     pub port: u16,
     pub nickname: String,
     #[serde(rename = "alternative_nicknames")] pub alt_nicknames: Option<Vec<String>>,
@@ -41,14 +42,37 @@ pub struct ServerCfg {
     pub database: String,
     #[serde(rename = "weather_api_secret")] pub weather_secret: Option<String>,
     #[serde(rename = "geocoding_api_key")] pub geocoding_key: Option<String>,
+    pub weather_provider: Option<WeatherService>,
+    pub openweathermap_api_key: Option<String>,
+    pub weather_api_bases: Option<HashMap<String, String>>,
+    pub geocoder: Option<GeocodingService>,
+    pub geocoding_api_bases: Option<HashMap<String, String>>,
+    pub geonames_file: Option<String>,
     pub wolframalpha_appid: Option<String>,
     #[serde(rename = "youtube_api_key")] pub youtube_key: Option<String>,
     pub google_search_id: Option<String>,
     pub google_search_key: Option<String>,
     pub max_burst_messages: Option<u32>,
     pub burst_window_length: Option<u32>,
+    pub queue_max_age: Option<u32>,
+    pub bulk_queue_max_age: Option<u32>,
+    pub continuation_marker: Option<bool>,
+    pub paste_after_lines: Option<usize>,
+    pub paste_endpoint: Option<String>,
+    pub paste_form_field: Option<String>,
+    pub paste_listen: Option<String>,
+    pub paste_base_url: Option<String>,
+    pub paste_max_age: Option<u64>,
+    pub paste_max_count: Option<usize>,
     pub owners: Vec<String>,
     pub wormy_nick: Option<String>,
+    pub mention_reply: Option<String>,
+    pub command_prefixes: Option<Vec<String>>,
+    pub max_pipeline_length: Option<usize>,
+    pub tell_max_pending: Option<usize>,
+    pub ctcp_replies: Option<HashMap<String, String>>,
+    pub ctcp_max_replies: Option<u32>,
+    pub ctcp_window_length: Option<u32>,
     #[serde(rename = "channel")] pub channels: Vec<ChannelCfg>,
     pub use_ssl: bool,
 }
@@ -59,10 +83,149 @@ pub struct ChannelCfg {
     pub name: String,
     pub password: Option<String>,
     pub url_blacklisted_domains: Option<Vec<String>>,
+    pub command_prefixes: Option<Vec<String>>,
+    pub max_lines: Option<usize>,
+    pub plain_text: Option<bool>,
+    pub paste_after_lines: Option<usize>,
+    pub reply_mode: Option<ReplyMode>,
+    pub reply_modes: Option<HashMap<String, ReplyMode>>,
+    pub tell_on_join: Option<bool>,
+    pub tell_private: Option<bool>,
+    pub weather_provider: Option<WeatherService>,
+    pub weather_units: Option<Units>,
+    pub weather_fields: Option<Vec<String>>,
     pub modules: Vec<String>,
 }
 
+/// How replies to a command in a channel are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplyMode {
+    /// PRIVMSG to the channel
+    Privmsg,
+    /// NOTICE to the channel
+    Notice,
+    /// NOTICE to the requester
+    PrivateNotice,
+    /// PRIVMSG to the requester
+    Private,
+    /// PRIVMSG to the channel, prefixed with the requester's nick
+    Highlight,
+}
+
+/// Where `.weather` gets its forecasts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WeatherService {
+    /// https://darksky.net, needs `weather_api_secret`
+    DarkSky,
+    /// https://open-meteo.com
+    OpenMeteo,
+    /// https://openweathermap.org, needs `openweathermap_api_key`
+    OpenWeatherMap,
+    /// https://api.met.no
+    MetNorway,
+}
+
+impl WeatherService {
+    /// The name used in the config, e.g. for `weather_api_bases`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            WeatherService::DarkSky => "dark-sky",
+            WeatherService::OpenMeteo => "open-meteo",
+            WeatherService::OpenWeatherMap => "open-weather-map",
+            WeatherService::MetNorway => "met-norway",
+        }
+    }
+}
+
+/// Units of `.weather` replies, named like Dark Sky's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// °C, km/h, mm, hPa
+    Metric,
+    /// °F, mph, in, inHg
+    Imperial,
+    /// °C, mph, mm, hPa
+    Uk,
+    /// °C, m/s, mm, hPa
+    Si,
+}
+
+impl Units {
+    pub fn parse(s: &str) -> Option<Units> {
+        match &*s.to_lowercase() {
+            "metric" => Some(Units::Metric),
+            "imperial" => Some(Units::Imperial),
+            "uk" => Some(Units::Uk),
+            "si" => Some(Units::Si),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Units::Metric => "metric",
+            Units::Imperial => "imperial",
+            Units::Uk => "uk",
+            Units::Si => "si",
+        }
+    }
+}
+
+/// Where `.weather` looks up locations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GeocodingService {
+    /// https://developer.mapquest.com, needs `geocoding_api_key`
+    MapQuest,
+    /// https://nominatim.openstreetmap.org
+    Nominatim,
+    /// https://photon.komoot.io
+    Photon,
+    /// Offline, from the GeoNames dump in `geonames_file`
+    GeoNames,
+}
+
+impl GeocodingService {
+    /// The name used in the config, and recorded in the geocode cache
+    pub fn name(&self) -> &'static str {
+        match *self {
+            GeocodingService::MapQuest => "mapquest",
+            GeocodingService::Nominatim => "nominatim",
+            GeocodingService::Photon => "photon",
+            GeocodingService::GeoNames => "geonames",
+        }
+    }
+}
+
 impl ServerCfg {
+    /// The geocoding service, MapQuest if there's a key for it for compatibility, otherwise
+    /// Nominatim, which needs none
+    pub fn geocoding_service(&self) -> GeocodingService {
+        self.geocoder.unwrap_or(if self.geocoding_key.is_some() {
+            GeocodingService::MapQuest
+        } else {
+            GeocodingService::Nominatim
+        })
+    }
+
+    /// The weather service used in `target`, Dark Sky if there's a secret for it for
+    /// compatibility, otherwise Open-Meteo, which needs no key
+    pub fn weather_service(&self, target: &str) -> WeatherService {
+        self.channels
+            .iter()
+            .find(|c| c.name == target)
+            .and_then(|c| c.weather_provider)
+            .or(self.weather_provider)
+            .unwrap_or(if self.weather_secret.is_some() {
+                WeatherService::DarkSky
+            } else {
+                WeatherService::OpenMeteo
+            })
+    }
+
     pub fn new_irc_client(&self) -> Result<IrcClient> {
         Ok(IrcClient::from_config(IrcConfig {
             nickname: Some(self.nickname.clone()),
@@ -87,16 +250,10 @@ impl ServerCfg {
                     Some(hm)
                 }
             },
-            max_messages_in_burst: self.max_burst_messages,
-            burst_window_length: self.burst_window_length,
+            // modules::queue throttles replies, the irc crate must not hold them back again
+            max_messages_in_burst: Some(u32::max_value()),
             encoding: Some("UTF-8".to_owned()),
             should_ghost: Some(true),
-            version: Some(format!(
-                "Parabot {} brought to you by {}",
-                env!("CARGO_PKG_VERSION"),
-                env!("CARGO_PKG_AUTHORS")
-            )),
-            source: Some("https://github.com/ParadoxSpiral/parabot".into()),
             ..Default::default()
         })?)
     }
@@ -105,13 +262,48 @@ impl ServerCfg {
 pub fn parse_config(input: &str) -> Result<Config> {
     let ret = de::from_str::<Config>(input)?;
     for srv in &ret.servers {
-        if (srv.weather_secret.is_none() || srv.geocoding_key.is_none())
-            && srv.channels
-                .iter()
-                .any(|c| c.modules.iter().any(|m| m == "weather"))
+        let weather_channels = srv.channels
+            .iter()
+            .filter(|c| c.modules.iter().any(|m| m == "weather"))
+            .collect::<Vec<_>>();
+        // PMs use the server's service
+        let weather_services = weather_channels
+            .iter()
+            .map(|c| srv.weather_service(&c.name))
+            .chain(if weather_channels.is_empty() {
+                None
+            } else {
+                Some(srv.weather_service(""))
+            })
+            .collect::<Vec<_>>();
+        if !weather_services.is_empty()
+            && srv.geocoding_service() == GeocodingService::MapQuest
+            && srv.geocoding_key.is_none()
+        {
+            panic!(
+                "MapQuest used on {:?}, but no geocoding key given",
+                &srv.address
+            );
+        } else if !weather_services.is_empty()
+            && srv.geocoding_service() == GeocodingService::GeoNames
+            && srv.geonames_file.is_none()
+        {
+            panic!(
+                "GeoNames used on {:?}, but no geonames_file given",
+                &srv.address
+            );
+        } else if weather_services.contains(&WeatherService::DarkSky)
+            && srv.weather_secret.is_none()
         {
             panic!(
-                "Weather module enabled on {:?}, but no weather API secret or geocoding key given",
+                "Dark Sky used on {:?}, but no weather API secret given",
+                &srv.address
+            );
+        } else if weather_services.contains(&WeatherService::OpenWeatherMap)
+            && srv.openweathermap_api_key.is_none()
+        {
+            panic!(
+                "OpenWeatherMap used on {:?}, but no API key given",
                 &srv.address
             );
         } else if srv.wolframalpha_appid.is_none()
@@ -150,6 +342,15 @@ pub fn parse_config(input: &str) -> Result<Config> {
                 "Google module enabled on {:?}, but no search id given",
                 &srv.address
             );
+        } else if srv.command_prefixes
+            .iter()
+            .chain(srv.channels.iter().filter_map(|c| c.command_prefixes.as_ref()))
+            .any(|ps| ps.iter().any(|p| p.trim().is_empty()))
+        {
+            panic!(
+                "Empty command prefix configured on {:?}",
+                &srv.address
+            );
         }
     }
     Ok(ret)
@@ -21,7 +21,6 @@ extern crate chrono_tz;
 extern crate crossbeam;
 extern crate ddg;
 extern crate encoding;
-extern crate forecast;
 extern crate html5ever;
 extern crate humansize;
 extern crate irc;
@@ -239,6 +238,25 @@ fn main() {
             let srv1 = srv.clone();
             let srv2 = srv.clone();
             let log = log.clone();
+            // Send replies queued by the modules
+            {
+                let cfg = cfg.clone();
+                let srv = srv.clone();
+                let log = log.clone();
+                scope.spawn(move |_| wait_err(modules::queue::run(&cfg, &srv, &log)));
+            }
+            // Serve pastes of long replies, if configured
+            {
+                let cfg = cfg.clone();
+                let log = log.clone();
+                scope.spawn(move |_| wait_err(modules::paste::run(&cfg, &log)));
+            }
+            // Drop expired tells
+            {
+                let cfg = cfg.clone();
+                let log = log.clone();
+                scope.spawn(move |_| wait_err(modules::tell::run(&cfg, &log)));
+            }
             scope.spawn(move |_| {
                 // Handle registration etc
                 wait_err(srv1.identify());
@@ -15,27 +15,35 @@
 // You should have received a copy of the GNU General Public License
 // along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
 
+use chrono::NaiveDateTime;
+
 use super::schema::*;
 
 #[derive(Debug, Queryable)]
 pub struct PendingTell {
-    pub date: String,
+    pub id: i32,
+    pub date: NaiveDateTime,
     pub server_addr: String,
     pub channel: Option<String>,
     pub source_nick: String,
     pub target_nick: String,
     pub message: String,
+    pub snoozed_until: Option<NaiveDateTime>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub source_network: Option<String>,
 }
 
 #[derive(Debug, Insertable)]
 #[table_name = "pending_tells"]
 pub struct NewPendingTell<'a> {
-    pub date: &'a str,
+    pub date: NaiveDateTime,
     pub server_addr: &'a str,
     pub channel: Option<&'a str>,
     pub source_nick: &'a str,
     pub target_nick: &'a str,
     pub message: &'a str,
+    pub expires_at: Option<NaiveDateTime>,
+    pub source_network: Option<&'a str>,
 }
 
 #[derive(Debug, Queryable)]
@@ -59,6 +67,7 @@ pub struct Geocode {
     pub latitude: f32,
     pub longitude: f32,
     pub reverse_location: String,
+    pub provider: String,
 }
 
 #[derive(Debug, Insertable)]
@@ -68,4 +77,67 @@ pub struct NewGeocode<'a> {
     pub latitude: f32,
     pub longitude: f32,
     pub reverse_location: &'a str,
+    pub provider: &'a str,
+}
+
+#[derive(Debug, Queryable)]
+pub struct CommandAlias {
+    pub server: String,
+    pub scope: String,
+    pub name: String,
+    pub expansion: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "command_aliases"]
+pub struct NewCommandAlias<'a> {
+    pub server: &'a str,
+    pub scope: &'a str,
+    pub name: &'a str,
+    pub expansion: &'a str,
+}
+
+#[derive(Debug, Queryable)]
+pub struct UserPreference {
+    pub server: String,
+    pub nick: String,
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "user_preferences"]
+pub struct NewUserPreference<'a> {
+    pub server: &'a str,
+    pub nick: &'a str,
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+#[derive(Debug, Queryable)]
+pub struct Geoname {
+    pub id: i32,
+    pub name: String,
+    pub country_code: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub population: i64,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "geonames"]
+pub struct NewGeoname<'a> {
+    pub id: i32,
+    pub name: &'a str,
+    pub country_code: &'a str,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub population: i64,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "geoname_names"]
+pub struct NewGeonameName<'a> {
+    pub name: &'a str,
+    pub geoname_id: i32,
 }
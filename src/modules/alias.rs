@@ -0,0 +1,367 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use diesel;
+use diesel::prelude::*;
+use parking_lot::RwLock;
+use slog::Logger;
+
+use std::collections::HashMap;
+
+use config::{Config, ServerCfg};
+use errors::*;
+use models;
+use schema;
+use schema::command_aliases::dsl;
+
+// Aliases may expand to other aliases, but not deeper than this
+const MAX_EXPANSION_DEPTH: usize = 8;
+
+lazy_static!{
+    // (server, scope, name) -> expansion, where scope is either a channel or a nick
+    static ref ALIASES: RwLock<HashMap<(String, String, String), String>> = {
+        RwLock::new(HashMap::new())
+    };
+}
+
+// Read DB to get init values
+pub fn init(cfg: &Config, log: &Logger) -> Result<()> {
+    let mut hm = ALIASES.write();
+    for srv in &cfg.servers {
+        let aliases = super::with_database(srv, |db| {
+            Ok(dsl::command_aliases
+                .filter(dsl::server.eq(&srv.address))
+                .load::<models::CommandAlias>(db)?)
+        })?;
+
+        info!(log, "Command aliases: {:?}", &aliases);
+        for a in aliases {
+            hm.insert((a.server, a.scope, a.name), a.expansion);
+        }
+    }
+    hm.shrink_to_fit();
+    Ok(())
+}
+
+/// Returns the expanded command, if it starts with an alias of the user or the channel.
+/// Placeholders are `$1`…`$9` for single arguments, `$*` for all, and `$nick`.
+pub fn expand(cfg: &ServerCfg, nick: &str, target: &str, command: &str) -> Option<String> {
+    let hm = ALIASES.read();
+    let nick_scope = super::irc_lowercase(nick);
+    expand_with(command, nick, |name| {
+        hm.get(&(cfg.address.clone(), nick_scope.clone(), name.to_owned()))
+            .or_else(|| hm.get(&(cfg.address.clone(), target.to_owned(), name.to_owned())))
+            .cloned()
+    })
+}
+
+/// Whether `name` is an alias of the user or the channel
+pub fn exists(cfg: &ServerCfg, nick: &str, target: &str, name: &str) -> bool {
+    let hm = ALIASES.read();
+    hm.contains_key(&(
+        cfg.address.clone(),
+        super::irc_lowercase(nick),
+        name.to_owned(),
+    )) || hm.contains_key(&(cfg.address.clone(), target.to_owned(), name.to_owned()))
+}
+
+// Expands the aliases `lookup` knows, until a builtin command is reached
+fn expand_with<F>(command: &str, nick: &str, lookup: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded = command.to_owned();
+    let mut seen = Vec::new();
+
+    while seen.len() < MAX_EXPANSION_DEPTH {
+        let (name, args) = {
+            let mut split = expanded.splitn(2, ' ');
+            (
+                split.next().unwrap().to_owned(),
+                split.next().unwrap_or("").trim().to_owned(),
+            )
+        };
+        // An alias that expands to itself, or a cycle, ends up at the builtin command
+        if seen.contains(&name) {
+            break;
+        }
+        if let Some(expansion) = lookup(&name) {
+            expanded = substitute(&expansion, &args, nick);
+            seen.push(name);
+        } else {
+            break;
+        }
+    }
+
+    if seen.is_empty() {
+        None
+    } else {
+        Some(expanded)
+    }
+}
+
+fn substitute(expansion: &str, args: &str, nick: &str) -> String {
+    let split = args.split_whitespace().collect::<Vec<_>>();
+    let mut used_args = false;
+    let mut ret = String::with_capacity(expansion.len() + args.len());
+    let mut chars = expansion.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            ret.push(c);
+            continue;
+        }
+        match chars.peek().cloned() {
+            Some('*') => {
+                chars.next();
+                used_args = true;
+                ret.push_str(args);
+            }
+            Some(d @ '1'..='9') => {
+                chars.next();
+                used_args = true;
+                let n = d.to_digit(10).unwrap() as usize;
+                if let Some(arg) = split.get(n - 1) {
+                    ret.push_str(arg);
+                }
+            }
+            _ if chars.clone().take(4).eq("nick".chars()) => {
+                for _ in 0..4 {
+                    chars.next();
+                }
+                ret.push_str(nick);
+            }
+            _ => ret.push(c),
+        }
+    }
+    // Without placeholders, arguments are appended, e.g. `wt` = `weather 1-3d`, `wt Tokyo`
+    if !used_args && !args.is_empty() {
+        ret.push(' ');
+        ret.push_str(args);
+    }
+    ret
+}
+
+pub fn handle(
+    cfg: &ServerCfg,
+    log: &Logger,
+    nick: &str,
+    target: &str,
+    args: &str,
+    prefix: &str,
+    private: bool,
+) -> Result<String> {
+    let mut split = args.splitn(2, ' ');
+    let sub = split.next().unwrap();
+    let rest = split.next().unwrap_or("").trim();
+
+    // `--channel` manages the aliases of the current channel, instead of the user's
+    let (channel_scope, rest) = if rest.starts_with("--channel") {
+        (true, rest[9..].trim())
+    } else {
+        (false, rest)
+    };
+    if channel_scope && private {
+        return Ok("Channel aliases can only be managed in a channel.".into());
+    } else if channel_scope && (sub == "add" || sub == "del")
+        && !cfg.owners.iter().any(|o| o == nick)
+    {
+        return Ok("Only my owners may manage channel aliases.".into());
+    }
+    let scope = if channel_scope {
+        target.to_owned()
+    } else {
+        super::irc_lowercase(nick)
+    };
+
+    match sub {
+        "add" => {
+            let mut split = rest.splitn(2, '=');
+            let (name, expansion) = match (split.next(), split.next()) {
+                (Some(name), Some(expansion))
+                    if !name.trim().is_empty() && !expansion.trim().is_empty() =>
+                {
+                    (name.trim(), expansion.trim())
+                }
+                _ => {
+                    trace!(log, "Invalid alias: {:?}", args);
+                    return Ok(format!(
+                        "Invalid `{0}alias` syntax, try: `{0}alias add [--channel] <name> = \
+                         <command>`",
+                        prefix
+                    ));
+                }
+            };
+            let expansion = if expansion.starts_with(prefix) {
+                &expansion[prefix.len()..]
+            } else {
+                expansion
+            };
+            if name.contains(char::is_whitespace) || name == "alias" {
+                return Ok(format!("`{}` can't be used as an alias name.", name));
+            }
+
+            let new = models::NewCommandAlias {
+                server: &cfg.address,
+                scope: &scope,
+                name: name,
+                expansion: expansion,
+            };
+            super::with_database(cfg, |db| {
+                diesel::replace_into(schema::command_aliases::table)
+                    .values(&new)
+                    .execute(db)?;
+                Ok(())
+            })?;
+            ALIASES.write().insert(
+                (cfg.address.clone(), scope.clone(), name.to_owned()),
+                expansion.to_owned(),
+            );
+
+            Ok(format!(
+                "{}: `{}{}` now expands to `{}{}`",
+                nick, prefix, name, prefix, expansion
+            ))
+        }
+        "del" => {
+            let name = rest;
+            let key = (cfg.address.clone(), scope.clone(), name.to_owned());
+            if !ALIASES.read().contains_key(&key) {
+                return Ok(format!("{}: There is no alias `{}`.", nick, name));
+            }
+            // Like `add`, the alias only changes in memory once the database changed
+            super::with_database(cfg, |db| {
+                diesel::delete(
+                    dsl::command_aliases
+                        .filter(dsl::server.eq(&cfg.address))
+                        .filter(dsl::scope.eq(&scope))
+                        .filter(dsl::name.eq(name)),
+                ).execute(db)?;
+                Ok(())
+            })?;
+            ALIASES.write().remove(&key);
+
+            Ok(format!("{}: Deleted alias `{}`.", nick, name))
+        }
+        "list" => {
+            let hm = ALIASES.read();
+            let mut list = hm.iter()
+                .filter(|&(&(ref server, ref s, _), _)| *server == cfg.address && *s == scope)
+                .map(|(&(_, _, ref name), expansion)| format!("{} = {}", name, expansion))
+                .collect::<Vec<_>>();
+            if list.is_empty() {
+                Ok(format!("{}: No aliases defined.", nick))
+            } else {
+                list.sort();
+                Ok(format!("{}: {}", nick, list.join("; ")))
+            }
+        }
+        _ => Ok(format!(
+            "Invalid `{0}alias` syntax, try: `{0}help alias`",
+            prefix
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases<'a>(list: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            list.iter()
+                .find(|&&(n, _)| n == name)
+                .map(|&(_, e)| e.to_owned())
+        }
+    }
+
+    #[test]
+    fn substitute_placeholders() {
+        assert_eq!(substitute("tell $1 $2", "bob hi there", "amy"), "tell bob hi");
+        assert_eq!(substitute("tell bob $*", "hi there", "amy"), "tell bob hi there");
+        assert_eq!(substitute("weather $nick", "", "amy"), "weather amy");
+        assert_eq!(substitute("g $2 $1", "a b", "amy"), "g b a");
+    }
+
+    #[test]
+    fn substitute_missing_args() {
+        assert_eq!(substitute("tell $1 $3", "bob", "amy"), "tell bob ");
+        // Placeholders are used, so nothing is appended
+        assert_eq!(substitute("g $1", "", "amy"), "g ");
+    }
+
+    #[test]
+    fn substitute_appends_without_placeholders() {
+        assert_eq!(substitute("weather 1-3d", "Tokyo", "amy"), "weather 1-3d Tokyo");
+        assert_eq!(substitute("weather 1-3d", "", "amy"), "weather 1-3d");
+        // `$nick` is no argument placeholder
+        assert_eq!(substitute("tell $nick", "hi", "amy"), "tell amy hi");
+    }
+
+    #[test]
+    fn substitute_literal_dollars() {
+        assert_eq!(substitute("calc 5$", "", "amy"), "calc 5$");
+        assert_eq!(substitute("calc $0 $x", "a", "amy"), "calc $0 $x a");
+        assert_eq!(substitute("g $nickname", "", "amy"), "g amyname");
+    }
+
+    #[test]
+    fn expand_builtin() {
+        assert_eq!(expand_with("weather Tokyo", "amy", aliases(&[])), None);
+    }
+
+    #[test]
+    fn expand_chained() {
+        let list = [("wt", "w 1-3d $*"), ("w", "weather")];
+        assert_eq!(
+            expand_with("wt Tokyo", "amy", aliases(&list)),
+            Some("weather 1-3d Tokyo".to_owned())
+        );
+    }
+
+    #[test]
+    fn expand_self_and_cycles() {
+        let list = [("weather", "weather 1-3d")];
+        assert_eq!(
+            expand_with("weather Tokyo", "amy", aliases(&list)),
+            Some("weather 1-3d Tokyo".to_owned())
+        );
+        let list = [("a", "b"), ("b", "a")];
+        assert_eq!(
+            expand_with("a x", "amy", aliases(&list)),
+            Some("a x".to_owned())
+        );
+    }
+
+    #[test]
+    fn expand_depth_limit() {
+        let list = [
+            ("a0", "a1"),
+            ("a1", "a2"),
+            ("a2", "a3"),
+            ("a3", "a4"),
+            ("a4", "a5"),
+            ("a5", "a6"),
+            ("a6", "a7"),
+            ("a7", "a8"),
+            ("a8", "a9"),
+        ];
+        assert_eq!(
+            expand_with("a0", "amy", aliases(&list)),
+            Some("a8".to_owned())
+        );
+    }
+}
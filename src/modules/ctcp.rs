@@ -0,0 +1,197 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use chrono::Utc;
+use irc::client::prelude::*;
+use parking_lot::{Mutex, RwLock};
+use slog::Logger;
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use config::ServerCfg;
+use errors::*;
+
+// Used if the bot owner did not configure a rate limit
+const DEFAULT_MAX_REPLIES: u32 = 3;
+const DEFAULT_WINDOW_LENGTH: u32 = 10;
+
+/// Takes the query's parameters, and returns the text to reply with, if any
+pub type CtcpHandler = fn(&ServerCfg, &str) -> Option<String>;
+
+/// Takes the message, its target, the action's text, and whether it was sent privately
+pub type ActionHandler = fn(&ServerCfg, &Logger, &Message, &str, &str, bool) -> Result<()>;
+
+lazy_static!{
+    static ref HANDLERS: RwLock<HashMap<String, CtcpHandler>> = {
+        RwLock::new(HashMap::new())
+    };
+    // (module, handler), a handler only sees actions in channels its module is enabled in
+    static ref ACTION_HANDLERS: RwLock<Vec<(String, ActionHandler)>> = {
+        RwLock::new(Vec::new())
+    };
+    static ref RECENT_REPLIES: Mutex<HashMap<String, VecDeque<Instant>>> = {
+        Mutex::new(HashMap::new())
+    };
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Ctcp<'a> {
+    Action(&'a str),
+    Query { verb: &'a str, params: &'a str },
+}
+
+pub fn init() {
+    register("VERSION", |_, _| {
+        Some(format!(
+            "Parabot {} brought to you by {}",
+            env!("CARGO_PKG_VERSION"),
+            env!("CARGO_PKG_AUTHORS")
+        ))
+    });
+    register("SOURCE", |_, _| {
+        Some("https://github.com/ParadoxSpiral/parabot".into())
+    });
+    register("PING", |_, params| Some(params.to_owned()));
+    register("TIME", |_, _| Some(Utc::now().to_rfc2822()));
+    register("CLIENTINFO", |cfg, _| {
+        let mut verbs = HANDLERS.read().keys().cloned().collect::<Vec<_>>();
+        if let Some(ref replies) = cfg.ctcp_replies {
+            verbs.extend(replies.keys().map(|v| v.to_uppercase()));
+        }
+        verbs.push("ACTION".into());
+        verbs.sort();
+        verbs.dedup();
+        Some(verbs.join(" "))
+    });
+}
+
+/// Make the bot answer CTCP queries with `verb`, replaces any previous handler
+pub fn register(verb: &str, handler: CtcpHandler) {
+    HANDLERS.write().insert(verb.to_uppercase(), handler);
+}
+
+/// Make `module` see ACTIONs, i.e. `/me …`
+pub fn on_action(module: &str, handler: ActionHandler) {
+    ACTION_HANDLERS.write().push((module.to_owned(), handler));
+}
+
+/// Returns None if the message is not a CTCP message
+pub fn parse(content: &str) -> Option<Ctcp> {
+    if !content.starts_with('\x01') {
+        return None;
+    }
+    let inner = content[1..].trim_end_matches('\x01');
+    let mut split = inner.splitn(2, ' ');
+    let verb = split.next().unwrap();
+    let params = split.next().unwrap_or("");
+    if verb == "ACTION" {
+        Some(Ctcp::Action(params))
+    } else {
+        Some(Ctcp::Query { verb, params })
+    }
+}
+
+pub fn handle_query(
+    cfg: &ServerCfg,
+    srv: &IrcClient,
+    log: &Logger,
+    nick: &str,
+    verb: &str,
+    params: &str,
+) -> Result<()> {
+    let verb = verb.to_uppercase();
+    let reply = if let Some(text) = cfg.ctcp_replies
+        .as_ref()
+        .and_then(|r| r.iter().find(|&(v, _)| v.to_uppercase() == verb))
+        .map(|(_, text)| text.clone())
+    {
+        Some(text)
+    } else {
+        let handler = HANDLERS.read().get(&verb).cloned();
+        handler.and_then(|h| h(cfg, params))
+    };
+
+    if let Some(reply) = reply {
+        if rate_limited(cfg) {
+            debug!(log, "Not replying to CTCP {} by {}, rate limited", verb, nick);
+        } else {
+            trace!(log, "Replying to CTCP {} by {}: {}", verb, nick, reply);
+            let reply = if reply.is_empty() {
+                format!("\x01{}\x01", verb)
+            } else {
+                format!("\x01{} {}\x01", verb, reply)
+            };
+            srv.send_notice(nick, &reply)?;
+        }
+    } else {
+        debug!(log, "Unknown CTCP {} by {}", verb, nick);
+    }
+    Ok(())
+}
+
+/// Passes an ACTION to the modules that registered for them
+pub fn handle_action(
+    cfg: &ServerCfg,
+    log: &Logger,
+    msg: &Message,
+    target: &str,
+    text: &str,
+    private: bool,
+) -> Result<()> {
+    debug!(
+        log,
+        "ACTION from {} to {}: {}",
+        msg.source_nickname().unwrap(),
+        target,
+        text
+    );
+
+    let handlers = ACTION_HANDLERS.read().clone();
+    for (module, handler) in handlers {
+        if private || super::module_enabled_channel(cfg, target, &module) {
+            handler(cfg, log, msg, target, text, private)?;
+        }
+    }
+    Ok(())
+}
+
+// Keeps track of replies per server, so that the bot can't be used to reflect a CTCP flood
+fn rate_limited(cfg: &ServerCfg) -> bool {
+    let max = cfg.ctcp_max_replies.unwrap_or(DEFAULT_MAX_REPLIES) as usize;
+    let window = Duration::from_secs(u64::from(
+        cfg.ctcp_window_length.unwrap_or(DEFAULT_WINDOW_LENGTH),
+    ));
+
+    let mut hm = RECENT_REPLIES.lock();
+    let recent = hm.entry(cfg.address.clone())
+        .or_insert_with(|| VecDeque::with_capacity(max));
+    let now = Instant::now();
+    while recent
+        .front()
+        .map(|t| now.duration_since(*t) > window)
+        .unwrap_or(false)
+    {
+        recent.pop_front();
+    }
+    if recent.len() >= max {
+        true
+    } else {
+        recent.push_back(now);
+        false
+    }
+}
@@ -21,6 +21,7 @@ use reqwest::Url;
 
 use config::ServerCfg;
 use errors::*;
+use super::format;
 
 pub fn handle(cfg: &ServerCfg, msg: &str, target: &str) -> Result<String> {
     let resp = Query::new(msg, "parabot").execute()?;
@@ -43,12 +44,17 @@ pub fn handle(cfg: &ServerCfg, msg: &str, target: &str) -> Result<String> {
                 if let RelatedTopic::TopicResult(TopicResult { ref text, .. }) = *related {
                     if n == 0 {
                         if resp.response_type == Type::Disambiguation {
-                            ret.push_str(&format!("\x021:\x02 {}", text));
+                            ret.push_str(&format!("{} {}", format::bold("1:"), text));
                         } else {
-                            ret.push_str(&format!("{}: \x021:\x02 {}", resp.abstract_url, text));
+                            ret.push_str(&format!(
+                                "{}: {} {}",
+                                resp.abstract_url,
+                                format::bold("1:"),
+                                text
+                            ));
                         }
                     } else {
-                        ret.push_str(&format!(" \x02{}:\x02 {}", n + 1, text));
+                        ret.push_str(&format!(" {}: {}", format::bold(n + 1), text));
                     }
                 } else {
                     unreachable!()
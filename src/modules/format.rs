@@ -0,0 +1,457 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+// IRC formatting codes, see https://modern.ircdocs.horse/formatting.html
+// This module only depends on std, so that it can be fuzzed on its own, see fuzz/
+
+use std::fmt;
+
+const BOLD: u8 = 0x02;
+const COLOR: u8 = 0x03;
+const HEX_COLOR: u8 = 0x04;
+const RESET: u8 = 0x0f;
+const MONOSPACE: u8 = 0x11;
+const REVERSE: u8 = 0x16;
+const ITALIC: u8 = 0x1d;
+const STRIKETHROUGH: u8 = 0x1e;
+const UNDERLINE: u8 = 0x1f;
+
+/// The most bytes `Style::codes_before` can return
+pub const MAX_CODES_BYTES: usize = 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// `\x03`, 0-99
+    Irc(u8),
+    /// `\x04`, RRGGBB
+    Hex(u32),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub monospace: bool,
+    pub reverse: bool,
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+}
+
+impl Style {
+    /// The codes that produce this style from unformatted text
+    pub fn codes(&self) -> String {
+        let mut ret = String::new();
+        // A background can only be set together with a foreground of the same kind,
+        // 99 is the default color
+        match (self.foreground, self.background) {
+            (Some(Color::Irc(fg)), Some(Color::Irc(bg))) => {
+                ret.push_str(&format!("\x03{:02},{:02}", fg, bg))
+            }
+            (None, Some(Color::Irc(bg))) => ret.push_str(&format!("\x0399,{:02}", bg)),
+            (Some(Color::Irc(fg)), _) => ret.push_str(&format!("\x03{:02}", fg)),
+            (Some(Color::Hex(fg)), Some(Color::Hex(bg))) => {
+                ret.push_str(&format!("\x04{:06X},{:06X}", fg, bg))
+            }
+            (Some(Color::Hex(fg)), _) => ret.push_str(&format!("\x04{:06X}", fg)),
+            (None, _) => {}
+        }
+        for &(active, code) in &[
+            (self.bold, BOLD),
+            (self.italic, ITALIC),
+            (self.underline, UNDERLINE),
+            (self.strikethrough, STRIKETHROUGH),
+            (self.monospace, MONOSPACE),
+            (self.reverse, REVERSE),
+        ] {
+            if active {
+                ret.push(code as char);
+            }
+        }
+        ret
+    }
+
+    /// The codes that produce this style in front of `text`, without `text` extending them
+    pub fn codes_before(&self, text: &str) -> String {
+        let mut ret = self.codes();
+        // `\x0304` followed by `,12` would set a background, an empty bold separates them
+        if ret.ends_with(|c: char| c.is_ascii_hexdigit()) && text.starts_with(',') {
+            ret.push(BOLD as char);
+            ret.push(BOLD as char);
+        }
+        ret
+    }
+
+    /// Wrap `inner` in this style
+    pub fn apply<T: fmt::Display>(self, inner: T) -> Styled<T> {
+        Styled { style: self, inner }
+    }
+
+    // Applies the code at the start of `s`, returns its length in bytes
+    fn apply_code(&mut self, s: &[u8]) -> usize {
+        match s[0] {
+            BOLD => self.bold = !self.bold,
+            ITALIC => self.italic = !self.italic,
+            UNDERLINE => self.underline = !self.underline,
+            STRIKETHROUGH => self.strikethrough = !self.strikethrough,
+            MONOSPACE => self.monospace = !self.monospace,
+            REVERSE => self.reverse = !self.reverse,
+            RESET => *self = Style::default(),
+            COLOR => return 1 + self.apply_color(&s[1..]),
+            HEX_COLOR => return 1 + self.apply_hex_color(&s[1..]),
+            _ => unreachable!(),
+        }
+        1
+    }
+
+    // `\x03[fg[,bg]]`, where fg and bg are 1-2 digits; without fg the colors are reset
+    fn apply_color(&mut self, s: &[u8]) -> usize {
+        let digits = |s: &[u8]| s.iter().take(2).take_while(|b| b.is_ascii_digit()).count();
+        let number = |s: &[u8]| s.iter().fold(0, |acc, b| acc * 10 + (b - b'0'));
+
+        let fg = digits(s);
+        if fg == 0 {
+            self.foreground = None;
+            self.background = None;
+            return 0;
+        }
+        self.foreground = Some(Color::Irc(number(&s[..fg])));
+        // Mixing both kinds of colors is not representable
+        if let Some(Color::Hex(_)) = self.background {
+            self.background = None;
+        }
+        if s.get(fg) == Some(&b',') {
+            let bg = digits(&s[fg + 1..]);
+            if bg != 0 {
+                self.background = Some(Color::Irc(number(&s[fg + 1..fg + 1 + bg])));
+                return fg + 1 + bg;
+            }
+        }
+        fg
+    }
+
+    // `\x04[RRGGBB[,RRGGBB]]`; without a valid fg the colors are reset
+    fn apply_hex_color(&mut self, s: &[u8]) -> usize {
+        let hex = |s: &[u8]| {
+            if s.len() >= 6 && s[..6].iter().all(|b| b.is_ascii_hexdigit()) {
+                // Only ASCII, so this can't fail
+                u32::from_str_radix(::std::str::from_utf8(&s[..6]).unwrap(), 16).ok()
+            } else {
+                None
+            }
+        };
+
+        if let Some(fg) = hex(s) {
+            self.foreground = Some(Color::Hex(fg));
+            if let Some(Color::Irc(_)) = self.background {
+                self.background = None;
+            }
+            if s.get(6) == Some(&b',') {
+                if let Some(bg) = hex(&s[7..]) {
+                    self.background = Some(Color::Hex(bg));
+                    return 13;
+                }
+            }
+            6
+        } else {
+            self.foreground = None;
+            self.background = None;
+            0
+        }
+    }
+}
+
+/// Text in a style, that is closed again after the text
+pub struct Styled<T> {
+    style: Style,
+    inner: T,
+}
+
+impl<T: fmt::Display> fmt::Display for Styled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let codes = self.style.codes();
+        write!(f, "{}{}", codes, self.inner)?;
+        // Toggles are closed by repeating them, colors by an empty color code
+        for b in codes.bytes().filter(|b| *b != COLOR && *b != HEX_COLOR) {
+            if is_code(b) {
+                write!(f, "{}", b as char)?;
+            }
+        }
+        if self.style.foreground.is_some() || self.style.background.is_some() {
+            write!(f, "\x03")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn bold<T: fmt::Display>(inner: T) -> Styled<T> {
+    Style {
+        bold: true,
+        ..Default::default()
+    }.apply(inner)
+}
+
+/// A run of text in a single style
+#[derive(Debug, PartialEq)]
+pub struct Span<'a> {
+    pub style: Style,
+    pub text: &'a str,
+}
+
+fn is_code(b: u8) -> bool {
+    match b {
+        BOLD | COLOR | HEX_COLOR | RESET | MONOSPACE | REVERSE | ITALIC | STRIKETHROUGH
+        | UNDERLINE => true,
+        _ => false,
+    }
+}
+
+/// Splits `s` into spans of the same style, given the style active at its start
+pub fn parse(start: Style, s: &str) -> Vec<Span> {
+    let bytes = s.as_bytes();
+    let mut spans = Vec::new();
+    let mut style = start;
+    let mut text_start = 0;
+    let mut i = 0;
+    // Codes and their parameters are ASCII, so every index sliced at is a char boundary
+    while i < bytes.len() {
+        if is_code(bytes[i]) {
+            if text_start < i {
+                spans.push(Span {
+                    style,
+                    text: &s[text_start..i],
+                });
+            }
+            i += style.apply_code(&bytes[i..]);
+            text_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    if text_start < bytes.len() {
+        spans.push(Span {
+            style,
+            text: &s[text_start..],
+        });
+    }
+    spans
+}
+
+/// `s` without any formatting codes
+pub fn strip(s: &str) -> String {
+    parse(Style::default(), s).iter().map(|s| s.text).collect()
+}
+
+/// The style active at the end of `s`, given the style active at its start
+pub fn end_style(start: Style, s: &str) -> Style {
+    let bytes = s.as_bytes();
+    let mut style = start;
+    let mut i = 0;
+    while i < bytes.len() {
+        if is_code(bytes[i]) {
+            i += style.apply_code(&bytes[i..]);
+        } else {
+            i += 1;
+        }
+    }
+    style
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_before_comma() {
+        let style = end_style(Style::default(), "\x0304red");
+        assert_eq!(style.codes_before("text"), "\x0304");
+        assert_eq!(style.codes_before(",12 text"), "\x0304\x02\x02");
+        // The reopened line keeps the foreground, and gets no background
+        let line = style.codes_before(",12 text") + ",12 text";
+        assert_eq!(end_style(Style::default(), &line), style);
+        assert_eq!(strip(&line), ",12 text");
+
+        let bold = end_style(Style::default(), "\x0304\x02red");
+        assert_eq!(bold.codes_before(",12"), "\x0304\x02");
+    }
+
+    fn irc(fg: Option<u8>, bg: Option<u8>) -> Style {
+        Style {
+            foreground: fg.map(Color::Irc),
+            background: bg.map(Color::Irc),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bare_color_resets() {
+        assert_eq!(
+            parse(Style::default(), "\x0304red\x03"),
+            vec![Span {
+                style: irc(Some(4), None),
+                text: "red",
+            }]
+        );
+        assert_eq!(end_style(Style::default(), "\x0304,12red\x03"), Style::default());
+        assert_eq!(
+            parse(irc(Some(4), Some(12)), "\x03plain"),
+            vec![Span {
+                style: Style::default(),
+                text: "plain",
+            }]
+        );
+    }
+
+    #[test]
+    fn irc_colors() {
+        assert_eq!(end_style(Style::default(), "\x035"), irc(Some(5), None));
+        assert_eq!(end_style(Style::default(), "\x0305"), irc(Some(5), None));
+        assert_eq!(end_style(Style::default(), "\x034,2"), irc(Some(4), Some(2)));
+        assert_eq!(end_style(Style::default(), "\x0304,12"), irc(Some(4), Some(12)));
+        // At most two digits belong to a color, and a comma without digits is text
+        assert_eq!(
+            parse(Style::default(), "\x03123"),
+            vec![Span {
+                style: irc(Some(12), None),
+                text: "3",
+            }]
+        );
+        assert_eq!(
+            parse(Style::default(), "\x0304,text"),
+            vec![Span {
+                style: irc(Some(4), None),
+                text: ",text",
+            }]
+        );
+        // A new foreground keeps the background
+        assert_eq!(end_style(irc(Some(4), Some(12)), "\x0307"), irc(Some(7), Some(12)));
+    }
+
+    #[test]
+    fn hex_colors() {
+        let style = Style {
+            foreground: Some(Color::Hex(0xFF0000)),
+            background: Some(Color::Hex(0x00FF00)),
+            ..Default::default()
+        };
+        assert_eq!(
+            parse(Style::default(), "\x04FF0000,00ff00ab"),
+            vec![Span { style, text: "ab" }]
+        );
+        assert_eq!(style.codes(), "\x04FF0000,00FF00");
+        // Invalid colors reset, the digits stay text
+        assert_eq!(
+            parse(style, "\x04FF00zz"),
+            vec![Span {
+                style: Style::default(),
+                text: "FF00zz",
+            }]
+        );
+        // Both kinds of colors can't be mixed
+        assert_eq!(end_style(style, "\x0304"), irc(Some(4), None));
+    }
+
+    #[test]
+    fn reset_and_toggles() {
+        let bold = Style {
+            bold: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse(Style::default(), "\x02a\x02b"),
+            vec![
+                Span {
+                    style: bold,
+                    text: "a",
+                },
+                Span {
+                    style: Style::default(),
+                    text: "b",
+                },
+            ]
+        );
+        assert_eq!(
+            parse(Style::default(), "\x02\x02\x1d\x1dx"),
+            vec![Span {
+                style: Style::default(),
+                text: "x",
+            }]
+        );
+        assert_eq!(
+            parse(Style::default(), "\x02\x1f\x0304a\x0fb"),
+            vec![
+                Span {
+                    style: Style {
+                        bold: true,
+                        underline: true,
+                        ..irc(Some(4), None)
+                    },
+                    text: "a",
+                },
+                Span {
+                    style: Style::default(),
+                    text: "b",
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        let styles = [
+            Style::default(),
+            irc(Some(4), None),
+            irc(Some(99), Some(12)),
+            Style {
+                bold: true,
+                italic: true,
+                underline: true,
+                strikethrough: true,
+                monospace: true,
+                reverse: true,
+                ..irc(Some(99), Some(99))
+            },
+            Style {
+                foreground: Some(Color::Hex(0xABCDEF)),
+                background: Some(Color::Hex(0x012345)),
+                ..Default::default()
+            },
+        ];
+        // A background alone is sent with the default foreground 99
+        assert_eq!(irc(None, Some(12)).codes(), "\x0399,12");
+        for style in &styles {
+            let codes = style.codes();
+            assert!(codes.len() <= MAX_CODES_BYTES);
+            assert_eq!(end_style(Style::default(), &codes), *style);
+            // Styled text is closed again
+            let styled = style.apply("text").to_string();
+            assert_eq!(end_style(Style::default(), &styled), Style::default());
+            assert_eq!(strip(&styled), "text");
+        }
+    }
+
+    #[test]
+    fn strip_mixed() {
+        assert_eq!(
+            strip("\x02bold\x02 \x0304,05red\x03 \x04FF0000hex\x0f \x1ditalic\x1d \x03,5"),
+            "bold red hex italic ,5"
+        );
+        assert_eq!(strip("ünïcode \x0312ü\x03"), "ünïcode ü");
+        assert_eq!(strip("\x03\x04\x0f"), "");
+    }
+}
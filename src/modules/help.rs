@@ -17,14 +17,20 @@
 
 use config::ServerCfg;
 
-pub fn handle(cfg: &ServerCfg, target: &str, msg: &str, private: bool) -> Option<String> {
-    if &msg[1..] == "help" {
+pub fn handle(
+    cfg: &ServerCfg,
+    target: &str,
+    msg: &str,
+    prefix: &str,
+    private: bool,
+) -> Option<String> {
+    if msg == "help" {
         if private {
-            Some(
-                "Hi! For more information, use .help <module>. You can use these modules: \
-                 `duckduckgo`, `url-info`, `weather`, `tell`."
-                    .to_owned(),
-            )
+            Some(format!(
+                "Hi! For more information, use {}help <module>. You can use these modules: \
+                 `duckduckgo`, `url-info`, `weather`, `tell`, `alias`.",
+                prefix
+            ))
         } else {
             let mut modules = cfg.channels
                 .iter()
@@ -34,50 +40,101 @@ pub fn handle(cfg: &ServerCfg, target: &str, msg: &str, private: bool) -> Option
                 .clone();
             modules.sort();
             Some(format!(
-                "For more information, use .help <module>. \
+                "For more information, use {}help <module>. \
                  Enabled modules: {:?}",
-                &modules
+                prefix, &modules
             ))
         }
     } else {
         // Starts with help, e.g. more args
-        match msg[6..].trim() {
-            "bots" | ".bots" => {
-                Some(".bots will (hopefully) cause all bots in the channel to reply.".to_owned())
-            }
-            "ddg" | ".ddg" => {
-                Some(".ddg <search> uses ddg's instant answer API to perform a search.".to_owned())
-            }
-            "tell" | ".tell" => Some(
-                ".tell <nick> <message> will tell the user with <nick> <message>, \
-                 when they join a channel shared with me."
-                    .to_owned(),
-            ),
-            "weather" | ".weather" => Some(
-                "`.weather [<n|x-y><d|h>] [location]` will show weather information \
-                 powered by Dark Sky. If you specify `n` and `d` xor `h`, data of the next \
+        let module = msg[4..].trim();
+        let module = if module.starts_with(prefix) {
+            &module[prefix.len()..]
+        } else {
+            module
+        };
+        match module {
+            "bots" => Some(format!(
+                "{}bots will (hopefully) cause all bots in the channel to reply.",
+                prefix
+            )),
+            "ddg" => Some(format!(
+                "{}ddg <search> uses ddg's instant answer API to perform a search.",
+                prefix
+            )),
+            "tell" | "tells" => Some(format!(
+                "{0}tell <nick> <message> will tell the user with <nick> <message>, \
+                 when they next speak in a channel shared with me. Separate several nicks or \
+                 channels with `,`, e.g. `{0}tell alice,#team hi`, and add `--expires <n><m|h|d>` \
+                 to drop the tell if it wasn't delivered in time. `<nick>@<network>` tells \
+                 users of other networks I'm on. `{0}tells sent` lists the \
+                 pending tells you sent, which you can change with `{0}tell edit <id> \
+                 <message>` or withdraw with `{0}tell cancel <id>`. `{0}tells` sends you your \
+                 pending tells by PM, `{0}tell snooze [<n><m|h|d>]` postpones them. \
+                 `{0}reply <message>` answers the last tell you received, and `{0}set receipts \
+                 on` tells you when your tells were delivered.",
+                prefix
+            )),
+            "weather" => Some(format!(
+                "`{0}weather [<n|x-y><d|h>] [location]` will show weather information. \
+                 If you specify `n` and `d` xor `h`, data of the next \
                  `n`th`d|h` will be replied with. Specifying a range of `x-y` will use data of \
-                 that range. Data is available for the next 168h, or 7d. If you omit `location`, \
-                 the location you last used will be used."
-                    .to_owned(),
-            ),
+                 that range. Times in words work too, before or after `location`: `now`, \
+                 `today`, `tonight`, `tomorrow`, `this weekend`, weekdays like `monday`, \
+                 `in 3 hours|days` and `next 3 hours|days`, in the location's time. \
+                 How far ahead data is available depends on the weather provider. \
+                 If you omit `location`, the location you last used will be used. \
+                 Compare up to 4 locations with e.g. `{0}weather tomorrow Berlin vs Tokyo`. \
+                 `{0}weather set units metric|imperial|uk|si` changes the units of your replies. \
+                 Add fields with e.g. `{0}weather +sun +uv Berlin`, available are `+sun`, \
+                 `+moon`, `+uv`, `+pressure`, `+visibility`, `+dewpoint` and `+wind` for the \
+                 wind's direction.",
+                prefix
+            )),
+            "astro" => Some(format!(
+                "`{0}astro [location]` shows sunrise, sunset, the length of the day and the \
+                 moon phase at a location, or the one you last used with `{0}weather`.",
+                prefix
+            )),
+            "alias" => Some(format!(
+                "`{0}alias add [--channel] <name> = <command>` lets you use `{0}<name>` in \
+                 place of `{0}<command>`. `$1`…`$9` are replaced with the given arguments, \
+                 `$*` with all of them, and `$nick` with yours. Without placeholders, \
+                 arguments are appended. `{0}alias list` and `{0}alias del <name>` manage your \
+                 aliases; `--channel` those of the channel, which only my owners may change.",
+                prefix
+            )),
+            "set" => Some(format!(
+                "`{0}set <preference> <value>` stores a preference of yours, `{0}set \
+                 <preference>` resets it, and `{0}set` lists yours. Preferences: {1}",
+                prefix,
+                super::prefs::describe()
+            )),
+            "reply" => Some(format!(
+                "`{0}reply <message>` answers the last tell you received.",
+                prefix
+            )),
+            "more" => Some(format!(
+                "If a reply is too long for the channel, {0}more shows the rest of it.",
+                prefix
+            )),
             "url-info" | "url" => Some(
                 "url-info fetches urls posted in the channel and displays their metadata, \
                  and, depending on the website, more. Current additional metadata: wolframalpha\
                  , jisho, youtube."
                     .to_owned(),
             ),
-            "who" | ".who" => Some(
+            "who" => Some(
                 "If parabot runs as part of a wormy hivemind(e.g. sees messages from other \
                  bots as his own nick), this will inform the user whether parabot answered the \
                  last command."
                     .to_owned(),
             ),
-            "choose" | ".choose" => Some(
-                ".choose x y\\ x \"z\" 'r' … will randomly pick one of the provided elements, \
-                 which are parsed via shlex."
-                    .to_owned(),
-            ),
+            "choose" => Some(format!(
+                "{}choose x y\\ x \"z\" 'r' … will randomly pick one of the provided elements, \
+                 which are parsed via shlex.",
+                prefix
+            )),
             _ => if super::module_enabled_channel(cfg, &*target, "wormy") {
                 None
             } else {
@@ -25,34 +25,64 @@ use regex::Regex;
 use reqwest::Url;
 use shlex;
 use slog::Logger;
-use unicode_segmentation::UnicodeSegmentation;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::process;
 use std::sync::atomic::{AtomicBool, Ordering};
 
-use config::{Config, ServerCfg};
+use config::{Config, ReplyMode, ServerCfg};
 use errors::*;
+use self::queue::{Kind, Priority};
 
+mod alias;
+mod ctcp;
 mod ddg;
+pub mod format;
 mod help;
-mod tell;
+pub mod paste;
+mod prefs;
+pub mod queue;
+mod segment;
+pub mod tell;
 pub mod url;
 mod weather;
 
-const COMMAND_MODIFIER: char = '.';
+const DEFAULT_COMMAND_PREFIX: &str = ".";
+// Separates the commands of a pipeline, e.g. `.g rust | tell bob`
+const PIPE: &str = " | ";
+// Commands that may follow a PIPE, otherwise it's part of an argument, e.g. `.tell bob a | b`
+const COMMANDS: &[&str] = &[
+    "alias", "astro", "bot", "bots", "choose", "ddg", "g", "help", "jisho", "more", "queue",
+    "reply", "set", "tell", "tells", "wa", "weather", "who",
+];
+const DEFAULT_MAX_PIPELINE_LENGTH: usize = 3;
 // The spec does not define a limit, but it's 500b in most cases. However, the server may
 // add crap to your message, you cannot know. Hopefully 30b is enough..
 const MESSAGE_BYTES_LIMIT: usize = 470;
+// Space for formatting that has to be reopened on a new line
+const RESERVED_CONTROL_BYTES: usize = format::MAX_CODES_BYTES;
+const CONTINUATION_MARKER: &str = "…";
 
 lazy_static!{
     static ref HOSTNAMES: RwLock<HashMap<String, String>> = {
         RwLock::new(HashMap::new())
     };
     static ref LAST_MESSAGE: AtomicBool = AtomicBool::new(false);
+    // (server, channel) of channels with mode +c, i.e. formatting is blocked
+    static ref NO_FORMATTING_CHANNELS: RwLock<HashSet<(String, String)>> = {
+        RwLock::new(HashSet::new())
+    };
+    // (server, target) -> lines that exceeded the target's max_lines
+    static ref OVERFLOW: Mutex<HashMap<(String, String), (Kind, VecDeque<String>)>> = {
+        Mutex::new(HashMap::new())
+    };
 }
 
 pub fn init(cfg: &Config, log: &Logger) -> Result<()> {
+    ctcp::init();
+    ctcp::on_action("url-info", handle_urls);
+    alias::init(cfg, log)?;
+    prefs::init(cfg, log)?;
     tell::init(cfg, log)?;
     weather::init(cfg, log)
 }
@@ -78,7 +108,6 @@ pub fn handle(cfg: &ServerCfg, srv: &IrcClient, log: &Logger, msg: &Message) ->
         | Command::Response(Response::RPL_ENDOFNAMES, ..)
         | Command::Response(Response::RPL_TOPIC, ..)
         | Command::PART(..)
-        | Command::ChannelMODE(..)
         | Command::PING(..)
         | Command::PONG(..)
         | Command::QUIT(..)
@@ -108,13 +137,36 @@ pub fn handle(cfg: &ServerCfg, srv: &IrcClient, log: &Logger, msg: &Message) ->
                 .entry(cfg.address.clone())
                 .or_insert_with(|| msg.prefix.as_ref().unwrap().clone());
         }
-        Command::JOIN(..) => {
+        Command::JOIN(ref chan, ..) => {
             // The case of the bot joining a channel is handled by RPL_NAMREPLY
             if msg.source_nickname().unwrap() != cfg.nickname {
                 // We don't check if the module is enabled, because it's our responsibility to
                 // deliver the msg asap without fail, even if the bot owner disabled the module;
                 // If they *really* want, they can clean the database
                 tell::handle_user_join(cfg, srv, log, msg)?;
+            } else {
+                // Ask for the channel modes, to know if formatting is blocked
+                srv.send(Command::Raw("MODE".to_owned(), vec![chan.clone()], None))?;
+            }
+        }
+        Command::ChannelMODE(ref chan, ref modes) => {
+            trace!(log, "{:?}", msg);
+            for mode in modes {
+                match *mode {
+                    Mode::Plus(ChannelMode::Unknown('c'), _) => {
+                        set_no_formatting(cfg, log, chan, true)
+                    }
+                    Mode::Minus(ChannelMode::Unknown('c'), _) => {
+                        set_no_formatting(cfg, log, chan, false)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Command::Response(Response::RPL_CHANNELMODEIS, ref args, ..) => {
+            // <nick> <channel> <modes> [<mode params>]
+            if let (Some(chan), Some(modes)) = (args.get(1), args.get(2)) {
+                set_no_formatting(cfg, log, chan, modes.contains('c'));
             }
         }
         Command::Response(Response::RPL_NAMREPLY, ..) => {
@@ -143,178 +195,83 @@ pub fn handle(cfg: &ServerCfg, srv: &IrcClient, log: &Logger, msg: &Message) ->
             let reply_target = msg.response_target().unwrap();
             let private = !(target == reply_target);
 
-            // Check if msg is a command, handle command/context modules
-            if content.chars().nth(0).unwrap() == COMMAND_MODIFIER {
-                if &content[1..] == "bots" || &content[1..] == "bot" {
-                    trace!(log, "Replying to .bots");
-                    let reply = format!(
-                        "Serving text/html since 2017, yours truly {:?} \
-                         For a list of commands, try `.help`",
-                        &cfg.owners
-                    );
-                    send_segmented_message(cfg, srv, log, reply_target, &reply)?;
-                } else if content[1..].starts_with("help") {
-                    trace!(log, "Replying to .help");
-                    if let Some(reply) = help::handle(cfg, &*target, content, private) {
-                        send_segmented_message(
-                            cfg,
-                            srv,
-                            log,
-                            msg.source_nickname().unwrap(),
-                            &reply,
-                        )?;
-                    }
-                } else if &content[1..] == "exit" || &content[1..] == "quit"
-                    || &content[1..] == "part"
-                {
-                    info!(log, "Exit requested!");
-                    process::exit(2);
-                } else if &content[1..] == "who" && module_enabled_channel(cfg, &*target, "wormy") {
-                    if LAST_MESSAGE.load(Ordering::Acquire) {
-                        send_segmented_message(
-                            cfg,
-                            srv,
-                            log,
-                            reply_target,
-                            "parabot of the hive replied to the last command/url",
-                        )?;
-                    }
-                } else if (private || module_enabled_channel(cfg, &*target, "tell"))
-                    && content[1..].starts_with("tell")
-                {
-                    trace!(log, "Starting .tell");
-                    let reply = tell::add(cfg, log, msg, private)?;
-                    send_segmented_message(cfg, srv, log, reply_target, &reply)?;
-                    if module_enabled_channel(cfg, &*target, "wormy") {
-                        LAST_MESSAGE.store(true, Ordering::Release);
-                    }
-                } else if (private || module_enabled_channel(cfg, &*target, "duckduckgo"))
-                    && content[1..].starts_with("ddg")
-                {
-                    trace!(log, "Starting .ddg");
-                    let reply = ddg::handle(cfg, content[4..].trim(), &*target)?;
-                    send_segmented_message(cfg, srv, log, reply_target, &reply)?;
-                    if module_enabled_channel(cfg, &*target, "wormy") {
-                        LAST_MESSAGE.store(true, Ordering::Release);
-                    }
-                } else if (private || module_enabled_channel(cfg, &*target, "google"))
-                    && content[1..].starts_with('g')
-                {
-                    trace!(log, "Starting .ddg !g");
-                    let reply = url::handle(
-                        cfg,
-                        Url::parse(
-                            &("https://encrypted.google.com/search?q=".to_owned()
-                                + content[2..].trim()),
-                        )?,
-                        &*target,
-                        false,
-                    )?;
-                    send_segmented_message(cfg, srv, log, reply_target, &reply)?;
-                    if module_enabled_channel(cfg, &*target, "wormy") {
-                        LAST_MESSAGE.store(true, Ordering::Release);
-                    }
-                } else if (private || module_enabled_channel(cfg, &*target, "wolframalpha"))
-                    && content[1..].starts_with("wa")
-                {
-                    trace!(log, "Starting .ddg !wa");
-                    let reply = url::handle(
+            let command = parse_command(cfg, srv, &*target, content);
+
+            // Deliver tells as soon as their target is seen, see JOIN
+            if delivers_tells(command) {
+                tell::handle_user_message(
+                    cfg,
+                    srv,
+                    log,
+                    msg.source_nickname().unwrap(),
+                    if private { None } else { Some(&*target) },
+                )?;
+            }
+
+            // CTCP messages are not commands, and must not trigger e.g. url-info
+            match ctcp::parse(content) {
+                Some(ctcp::Ctcp::Action(text)) => {
+                    return ctcp::handle_action(cfg, log, msg, &*target, text, private);
+                }
+                Some(ctcp::Ctcp::Query { verb, params }) => {
+                    return ctcp::handle_query(
                         cfg,
-                        Url::parse(
-                            &("https://www.wolframalpha.com/input/?i=".to_owned()
-                                + content[3..].trim()),
-                        )?,
-                        &*target,
-                        false,
-                    )?;
-                    send_segmented_message(cfg, srv, log, reply_target, &reply)?;
-                    if module_enabled_channel(cfg, &*target, "wormy") {
-                        LAST_MESSAGE.store(true, Ordering::Release);
+                        srv,
+                        log,
+                        msg.source_nickname().unwrap(),
+                        verb,
+                        params,
+                    );
+                }
+                None => {}
+            }
+
+            // Check if msg is a command, handle command/context modules
+            if let Some(command) = command {
+                let reply = if command.is_empty() {
+                    trace!(log, "Replying to mention");
+                    if let Some(ref reply) = cfg.mention_reply {
+                        Some(("core", reply.clone()))
+                    } else {
+                        Some((
+                            "core",
+                            format!(
+                                "Hi! For a list of commands, try `{}help`",
+                                command_prefixes(cfg, &*target)[0]
+                            ),
+                        ))
                     }
-                } else if (private || module_enabled_channel(cfg, &*target, "jisho"))
-                    && content[1..].starts_with("jisho")
-                {
-                    trace!(log, "Starting .ddg !jisho");
-                    let reply = url::handle(
+                } else if let Some(steps) = pipeline_steps(command, |name| {
+                    COMMANDS.contains(&name)
+                        || (private || module_enabled_channel(cfg, &*target, "alias"))
+                            && alias::exists(cfg, msg.source_nickname().unwrap(), &*target, name)
+                }) {
+                    run_pipeline(cfg, srv, log, msg, &*target, private, &steps)?
+                } else {
+                    run_command(cfg, srv, log, msg, &*target, private, command)?
+                };
+                if let Some((module, reply)) = reply {
+                    let nick = msg.source_nickname().unwrap();
+                    let reply = if prefs::plain_text(cfg, nick) {
+                        format::strip(&reply)
+                    } else {
+                        reply
+                    };
+                    send_segmented_message(
                         cfg,
-                        Url::parse(&("http://jisho.org/search/".to_owned() + content[6..].trim()))?,
-                        &*target,
-                        false,
+                        log,
+                        reply_target,
+                        Some(nick),
+                        module,
+                        &reply,
+                        Priority::Interactive,
                     )?;
-                    send_segmented_message(cfg, srv, log, reply_target, &reply)?;
                     if module_enabled_channel(cfg, &*target, "wormy") {
                         LAST_MESSAGE.store(true, Ordering::Release);
                     }
-                } else if (private || module_enabled_channel(cfg, &*target, "weather"))
-                    && content[1..].starts_with("weather")
-                {
-                    trace!(log, "Starting .weather");
-                    let nick = msg.source_nickname().unwrap();
-                    let reply = weather::handle(cfg, srv, log, &content[8..], nick)?;
-                    send_segmented_message(cfg, srv, log, reply_target, &reply)?;
-                    if module_enabled_channel(cfg, &*target, "wormy") {
-                        LAST_MESSAGE.store(true, Ordering::Release);
-                    }
-                } else if (private || module_enabled_channel(cfg, &*target, "choose"))
-                    && content[1..].starts_with("choose")
-                {
-                    trace!(log, "Starting .choose");
-                    let opts = shlex::split(&content[8..]).unwrap();
-                    let reply = opts.choose(&mut thread_rng()).unwrap();
-                    send_segmented_message(cfg, srv, log, reply_target, &reply)?;
-                    if module_enabled_channel(cfg, &*target, "wormy") {
-                        LAST_MESSAGE.store(true, Ordering::Release);
-                    }
-                } else {
-                    debug!(log, "Unknown command {}", &content[1..]);
                 }
             } else if private || module_enabled_channel(cfg, &*target, "url-info") {
-                lazy_static! (
-                    static ref URL_REGEX: Regex = Regex::new("\
-                        .*?\
-                        (?:\
-                            (?:<){0,}(?P<url>\
-                                (?:(?:http)|(?:https))://\
-                                (?:[^\\s>]*?\\.){1,}\
-                                [^\\s>]*\
-                            )(?:>){0,})\
-                        .*?").unwrap();
-                );
-                let replies = URL_REGEX
-                    .captures_iter(content)
-                    .collect::<Vec<_>>()
-                    .par_iter()
-                    .map(|cap| {
-                        let url = Url::parse(cap.name("url").unwrap().as_str()).unwrap();
-                        trace!(log, "URL match: {:?}", url);
-                        if private || !cfg.channels.iter().any(|c| {
-                            let domain = url.domain().unwrap();
-                            *c.name == *target
-                                && c.url_blacklisted_domains
-                                    .iter()
-                                    .any(|ds| ds.iter().any(|d| *d == *domain))
-                        }) {
-                            Some(url::handle(cfg, url, &*target, true))
-                        } else {
-                            None
-                        }
-                    })
-                    .filter_map(|e| e)
-                    .collect::<Vec<Result<String>>>();
-                for reply in replies.into_iter() {
-                    match reply {
-                        Ok(reply) => {
-                            let reply_target = msg.response_target().unwrap();
-                            send_segmented_message(cfg, srv, log, reply_target, &reply).unwrap();
-                            if module_enabled_channel(cfg, &*target, "wormy") {
-                                LAST_MESSAGE.store(true, Ordering::Release);
-                            }
-                        }
-                        Err(e) => {
-                            crit!(log, "{:?}", e);
-                        }
-                    }
-                }
+                handle_urls(cfg, log, msg, &*target, content, private)?;
             }
         }
         _ => {
@@ -324,6 +281,476 @@ pub fn handle(cfg: &ServerCfg, srv: &IrcClient, log: &Logger, msg: &Message) ->
     Ok(())
 }
 
+// Whether tells are delivered before running `command`. Commands managing tells run first,
+// or e.g. `.tell snooze` would only get the tells it should postpone delivered.
+fn delivers_tells(command: Option<&str>) -> bool {
+    let (name, args) = split_command(command.unwrap_or(""));
+    match name {
+        "tells" => false,
+        "tell" => match split_command(args).0 {
+            "cancel" | "edit" | "snooze" => false,
+            _ => true,
+        },
+        _ => true,
+    }
+}
+
+// Splits `a | b | c` into its steps, if each step after the first starts with a command
+fn pipeline_steps<F>(command: &str, is_command: F) -> Option<Vec<&str>>
+where
+    F: Fn(&str) -> bool,
+{
+    if !command.contains(PIPE) {
+        return None;
+    }
+    let steps = command.split(PIPE).map(str::trim).collect::<Vec<_>>();
+    if steps[1..].iter().all(|step| {
+        step.split_whitespace()
+            .next()
+            .map_or(false, |name| is_command(name))
+    }) {
+        Some(steps)
+    } else {
+        None
+    }
+}
+
+// Runs each command of `a | b | c`, passing the reply of a command as argument to the next one
+fn run_pipeline(
+    cfg: &ServerCfg,
+    srv: &IrcClient,
+    log: &Logger,
+    msg: &Message,
+    target: &str,
+    private: bool,
+    steps: &[&str],
+) -> Result<Option<(&'static str, String)>> {
+    let max = cfg.max_pipeline_length.unwrap_or(DEFAULT_MAX_PIPELINE_LENGTH);
+    if steps.len() > max {
+        return Ok(Some((
+            "core",
+            format!("Sorry, pipelines are limited to {} commands.", max),
+        )));
+    }
+
+    let prefix = command_prefixes(cfg, target)[0];
+    let mut output: Option<(&'static str, String)> = None;
+    for (n, step) in steps.iter().enumerate() {
+        let step_command = if let Some((_, ref out)) = output {
+            format!("{} {}", step, out)
+        } else {
+            (*step).to_owned()
+        };
+        trace!(log, "Pipeline step {}: {:?}", n + 1, step_command);
+
+        match run_command(cfg, srv, log, msg, target, private, &step_command) {
+            Ok(Some(reply)) => output = Some(reply),
+            Ok(None) => {
+                return Ok(Some((
+                    "core",
+                    format!(
+                        "Step {} (`{}{}`) of the pipeline produced no output.",
+                        n + 1,
+                        prefix,
+                        step
+                    ),
+                )))
+            }
+            Err(e) => {
+                crit!(log, "Pipeline step {} failed: {:?}", n + 1, e);
+                return Ok(Some((
+                    "core",
+                    format!(
+                        "Step {} (`{}{}`) of the pipeline failed.",
+                        n + 1,
+                        prefix,
+                        step
+                    ),
+                )));
+            }
+        }
+    }
+    Ok(output)
+}
+
+// Returns the module and reply to the command, if it has one that should be sent to the channel
+fn run_command(
+    cfg: &ServerCfg,
+    srv: &IrcClient,
+    log: &Logger,
+    msg: &Message,
+    target: &str,
+    private: bool,
+    command: &str,
+) -> Result<Option<(&'static str, String)>> {
+    let prefix = command_prefixes(cfg, target)[0];
+    let nick = msg.source_nickname().unwrap();
+
+    // Expand aliases before routing, except when managing them
+    let expanded = if (private || module_enabled_channel(cfg, target, "alias"))
+        && split_command(command).0 != "alias"
+    {
+        alias::expand(cfg, nick, target, command)
+    } else {
+        None
+    };
+    if let Some(ref expanded) = expanded {
+        trace!(log, "Expanded alias {:?} to {:?}", command, expanded);
+    }
+    let command = expanded.as_ref().map(|e| &**e).unwrap_or(command);
+    // Commands are matched by their whole name, so e.g. `parabot: good bot` is no search
+    let (name, args) = split_command(command);
+
+    if name == "bots" || name == "bot" {
+        trace!(log, "Replying to .bots");
+        let reply = format!(
+            "Serving text/html since 2017, yours truly {:?} \
+             For a list of commands, try `{}help`",
+            &cfg.owners, prefix
+        );
+        Ok(Some(("core", reply)))
+    } else if name == "help" {
+        trace!(log, "Replying to .help");
+        if let Some(reply) = help::handle(cfg, target, command, prefix, private) {
+            send_segmented_message(
+                cfg,
+                log,
+                nick,
+                None,
+                "help",
+                &reply,
+                Priority::Interactive,
+            )?;
+        }
+        Ok(None)
+    } else if name == "exit" || name == "quit" || name == "part" {
+        info!(log, "Exit requested!");
+        process::exit(2);
+    } else if name == "more" {
+        trace!(log, "Replying to .more");
+        // Replies may have been sent to the requester, depending on the reply mode
+        let reply_target = msg.response_target().unwrap();
+        if send_more(cfg, log, reply_target, Priority::Interactive)
+            || (!private && send_more(cfg, log, nick, Priority::Interactive))
+        {
+            Ok(None)
+        } else {
+            Ok(Some(("core", "Nothing more to show.".to_owned())))
+        }
+    } else if name == "queue" && cfg.owners.iter().any(|o| o == nick) {
+        trace!(log, "Replying to .queue");
+        let (interactive, bulk) = queue::depth(cfg);
+        Ok(Some((
+            "core",
+            format!(
+                "{} interactive and {} bulk lines queued.",
+                interactive, bulk
+            ),
+        )))
+    } else if name == "set" {
+        trace!(log, "Replying to .set");
+        let reply = prefs::handle(cfg, log, nick, args, prefix)?;
+        Ok(Some(("core", reply)))
+    } else if name == "who" && module_enabled_channel(cfg, target, "wormy") {
+        if LAST_MESSAGE.load(Ordering::Acquire) {
+            Ok(Some((
+                "wormy",
+                "parabot of the hive replied to the last command/url".to_owned(),
+            )))
+        } else {
+            Ok(None)
+        }
+    } else if (private || module_enabled_channel(cfg, target, "alias"))
+        && name == "alias"
+    {
+        trace!(log, "Starting .alias");
+        let reply = alias::handle(
+            cfg,
+            log,
+            nick,
+            target,
+            args,
+            prefix,
+            private,
+        )?;
+        Ok(Some(("alias", reply)))
+    } else if (private || module_enabled_channel(cfg, target, "tell"))
+        && name == "reply"
+    {
+        trace!(log, "Starting .reply");
+        let reply = tell::reply(cfg, log, msg, args, prefix, private)?;
+        Ok(Some(("tell", reply)))
+    } else if (private || module_enabled_channel(cfg, target, "tell"))
+        && name == "tells"
+    {
+        trace!(log, "Starting .tells");
+        let reply = tell::handle_tells(cfg, srv, log, nick, args, prefix)?;
+        Ok(Some(("tell", reply)))
+    } else if (private || module_enabled_channel(cfg, target, "tell"))
+        && name == "tell"
+    {
+        trace!(log, "Starting .tell");
+        let reply = tell::handle(cfg, log, msg, args, prefix, private)?;
+        Ok(Some(("tell", reply)))
+    } else if (private || module_enabled_channel(cfg, target, "duckduckgo"))
+        && name == "ddg"
+    {
+        trace!(log, "Starting .ddg");
+        let reply = ddg::handle(cfg, args, target)?;
+        Ok(Some(("duckduckgo", reply)))
+    } else if (private || module_enabled_channel(cfg, target, "google"))
+        && name == "g"
+    {
+        trace!(log, "Starting .ddg !g");
+        let reply = url::handle(
+            cfg,
+            Url::parse(
+                &("https://encrypted.google.com/search?q=".to_owned()
+                    + args),
+            )?,
+            target,
+            false,
+        )?;
+        Ok(Some(("google", reply)))
+    } else if (private || module_enabled_channel(cfg, target, "wolframalpha"))
+        && name == "wa"
+    {
+        trace!(log, "Starting .ddg !wa");
+        let reply = url::handle(
+            cfg,
+            Url::parse(
+                &("https://www.wolframalpha.com/input/?i=".to_owned()
+                    + args),
+            )?,
+            target,
+            false,
+        )?;
+        Ok(Some(("wolframalpha", reply)))
+    } else if (private || module_enabled_channel(cfg, target, "jisho"))
+        && name == "jisho"
+    {
+        trace!(log, "Starting .ddg !jisho");
+        let reply = url::handle(
+            cfg,
+            Url::parse(&("http://jisho.org/search/".to_owned() + args))?,
+            target,
+            false,
+        )?;
+        Ok(Some(("jisho", reply)))
+    } else if (private || module_enabled_channel(cfg, target, "weather"))
+        && name == "weather"
+    {
+        trace!(log, "Starting .weather");
+        let reply = weather::handle(cfg, log, args, nick, target, prefix)?;
+        Ok(Some(("weather", reply)))
+    } else if (private || module_enabled_channel(cfg, target, "weather"))
+        && name == "astro"
+    {
+        trace!(log, "Starting .astro");
+        let reply = weather::astro(cfg, log, args, nick, prefix)?;
+        Ok(Some(("weather", reply)))
+    } else if (private || module_enabled_channel(cfg, target, "choose"))
+        && name == "choose"
+    {
+        trace!(log, "Starting .choose");
+        let opts = shlex::split(args).unwrap();
+        let reply = opts.choose(&mut thread_rng()).unwrap();
+        Ok(Some(("choose", reply.clone())))
+    } else {
+        debug!(log, "Unknown command {}", command);
+        Ok(None)
+    }
+}
+
+fn handle_urls(
+    cfg: &ServerCfg,
+    log: &Logger,
+    msg: &Message,
+    target: &str,
+    content: &str,
+    private: bool,
+) -> Result<()> {
+    lazy_static! (
+        static ref URL_REGEX: Regex = Regex::new("\
+            .*?\
+            (?:\
+                (?:<){0,}(?P<url>\
+                    (?:(?:http)|(?:https))://\
+                    (?:[^\\s>]*?\\.){1,}\
+                    [^\\s>]*\
+                )(?:>){0,})\
+            .*?").unwrap();
+    );
+    let replies = URL_REGEX
+        .captures_iter(content)
+        .collect::<Vec<_>>()
+        .par_iter()
+        .map(|cap| {
+            let url = Url::parse(cap.name("url").unwrap().as_str()).unwrap();
+            trace!(log, "URL match: {:?}", url);
+            if private || !cfg.channels.iter().any(|c| {
+                let domain = url.domain().unwrap();
+                *c.name == *target
+                    && c.url_blacklisted_domains
+                        .iter()
+                        .any(|ds| ds.iter().any(|d| *d == *domain))
+            }) {
+                Some(url::handle(cfg, url, &*target, true))
+            } else {
+                None
+            }
+        })
+        .filter_map(|e| e)
+        .collect::<Vec<Result<String>>>();
+    for reply in replies.into_iter() {
+        match reply {
+            Ok(reply) => {
+                let reply_target = msg.response_target().unwrap();
+                send_segmented_message(
+                    cfg,
+                    log,
+                    reply_target,
+                    msg.source_nickname(),
+                    "url-info",
+                    &reply,
+                    Priority::Interactive,
+                ).unwrap();
+                if module_enabled_channel(cfg, &*target, "wormy") {
+                    LAST_MESSAGE.store(true, Ordering::Release);
+                }
+            }
+            Err(e) => {
+                crit!(log, "{:?}", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+// The prefixes of the channel, or else of the server, or else the default prefix.
+// The first one is used when rendering usage hints.
+fn command_prefixes<'a>(cfg: &'a ServerCfg, target: &str) -> Vec<&'a str> {
+    let prefixes = cfg.channels
+        .iter()
+        .find(|c| c.name == target)
+        .and_then(|c| c.command_prefixes.as_ref())
+        .or_else(|| cfg.command_prefixes.as_ref())
+        .map(|ps| ps.iter().map(|p| &**p).collect::<Vec<_>>())
+        .unwrap_or_default();
+    if prefixes.is_empty() {
+        vec![DEFAULT_COMMAND_PREFIX]
+    } else {
+        prefixes
+    }
+}
+
+// Returns the command without its prefix, or without the bot's nick if addressed directly,
+// e.g. `parabot: weather berlin`. A bare mention returns an empty command.
+fn parse_command<'a>(
+    cfg: &ServerCfg,
+    srv: &IrcClient,
+    target: &str,
+    content: &'a str,
+) -> Option<&'a str> {
+    strip_command(
+        &command_prefixes(cfg, target),
+        |nick| is_own_nick(cfg, srv, nick),
+        content,
+    )
+}
+
+fn strip_command<'a, F>(prefixes: &[&str], is_own_nick: F, content: &'a str) -> Option<&'a str>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut prefixes = prefixes.to_vec();
+    // Prefer the longest match, e.g. `>>` over `>`
+    prefixes.sort_by_key(|p| ::std::cmp::Reverse(p.len()));
+    let strip_prefix = |s: &'a str| {
+        prefixes
+            .iter()
+            .find(|p| s.starts_with(*p))
+            .map(|p| &s[p.len()..])
+    };
+
+    // A lone prefix, e.g. `.`, is not a command; only mentions may be empty
+    if let Some(command) = strip_prefix(content) {
+        return if command.trim().is_empty() {
+            None
+        } else {
+            Some(command)
+        };
+    }
+
+    let trimmed = content.trim();
+    if is_own_nick(trimmed) {
+        return Some("");
+    }
+    let end = trimmed.find(|c| c == ':' || c == ',')?;
+    if is_own_nick(&trimmed[..end]) {
+        let command = trimmed[end + 1..].trim_start();
+        Some(strip_prefix(command).unwrap_or(command))
+    } else {
+        None
+    }
+}
+
+// Splits a command into its name and arguments, e.g. `g rust` into `g` and `rust`
+fn split_command(command: &str) -> (&str, &str) {
+    let command = command.trim_start();
+    match command.find(char::is_whitespace) {
+        Some(i) => (&command[..i], command[i..].trim()),
+        None => (command, ""),
+    }
+}
+
+fn is_own_nick(cfg: &ServerCfg, srv: &IrcClient, nick: &str) -> bool {
+    let nick = irc_lowercase(nick);
+    irc_lowercase(srv.current_nickname()) == nick || irc_lowercase(&cfg.nickname) == nick
+        || cfg.alt_nicknames
+            .iter()
+            .flat_map(|ns| ns.iter())
+            .any(|n| irc_lowercase(n) == nick)
+}
+
+// Nicks are case insensitive, using the rfc1459 casemapping
+fn irc_lowercase(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '[' => '{',
+            ']' => '}',
+            '\\' => '|',
+            '~' => '^',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+fn set_no_formatting(cfg: &ServerCfg, log: &Logger, chan: &str, blocked: bool) {
+    debug!(log, "Formatting blocked in {}: {}", chan, blocked);
+    let key = (cfg.address.clone(), chan.to_owned());
+    if blocked {
+        NO_FORMATTING_CHANNELS.write().insert(key);
+    } else {
+        NO_FORMATTING_CHANNELS.write().remove(&key);
+    }
+}
+
+// Whether replies to `target` must not contain formatting, because the channel is +c or
+// configured so, or the user opted out
+fn plain_text(cfg: &ServerCfg, target: &str) -> bool {
+    let configured = cfg.channels
+        .iter()
+        .find(|c| c.name == target)
+        .and_then(|c| c.plain_text)
+        .unwrap_or(false);
+    configured
+        || NO_FORMATTING_CHANNELS
+            .read()
+            .contains(&(cfg.address.clone(), target.to_owned()))
+        || prefs::plain_text(cfg, target)
+}
+
 fn module_enabled_channel(cfg: &ServerCfg, target: &str, module: &str) -> bool {
     cfg.channels
         .iter()
@@ -366,198 +793,325 @@ where
     }
 }
 
+// The reply mode of `module` in `target`, PMs are always answered with PRIVMSGs
+fn reply_mode(cfg: &ServerCfg, target: &str, module: &str) -> ReplyMode {
+    cfg.channels
+        .iter()
+        .find(|c| c.name == target)
+        .and_then(|c| {
+            c.reply_modes
+                .as_ref()
+                .and_then(|ms| ms.get(module))
+                .cloned()
+                .or(c.reply_mode)
+        })
+        .unwrap_or(ReplyMode::Privmsg)
+}
+
+// Sends as many lines as the target's `max_lines` allows, keeps the rest for `.more`.
+// Replies to a `requester` are sent according to the reply mode of `module` in `target`.
 fn send_segmented_message(
     cfg: &ServerCfg,
-    srv: &IrcClient,
     log: &Logger,
     target: &str,
+    requester: Option<&str>,
+    module: &str,
     msg: &str,
+    priority: Priority,
 ) -> Result<()> {
-    let msg_bytes = msg.bytes().len();
-    // :<hostname> PRIVMSG <target> :\u{200B}<message>
-    let fix_bytes = 1 + HOSTNAMES.read().get(&cfg.address).unwrap().bytes().len() + 9
-        + target.bytes().len() + 3;
-    trace!(log, "Msg bytes: {}; Fix bytes: {}", msg_bytes, fix_bytes);
+    let (target, kind, msg) = address(cfg, target, requester, module, msg);
+    send_reply(cfg, log, target, kind, msg, priority, None)
+}
 
-    let send = |msg: &str| srv.send_privmsg(target, &msg.replace('\n', " "));
+// Like `send_segmented_message` without a requester, `on_sent` is called once the lines that
+// are not kept for `.more` were sent, or dropped from the queue
+fn send_segmented_message_then(
+    cfg: &ServerCfg,
+    log: &Logger,
+    target: &str,
+    module: &str,
+    msg: &str,
+    priority: Priority,
+    on_sent: queue::OnSent,
+) -> Result<()> {
+    let (target, kind, msg) = address(cfg, target, None, module, msg);
+    send_reply(cfg, log, target, kind, msg, priority, Some(on_sent))
+}
+
+// The target, command and text of a reply, according to the reply mode
+fn address<'a>(
+    cfg: &ServerCfg,
+    target: &'a str,
+    requester: Option<&'a str>,
+    module: &str,
+    msg: &str,
+) -> (&'a str, Kind, String) {
+    match (reply_mode(cfg, target, module), requester) {
+        (ReplyMode::Notice, _) | (ReplyMode::PrivateNotice, None) => {
+            (target, Kind::Notice, msg.to_owned())
+        }
+        (ReplyMode::PrivateNotice, Some(nick)) => (nick, Kind::Notice, msg.to_owned()),
+        (ReplyMode::Private, Some(nick)) => (nick, Kind::Privmsg, msg.to_owned()),
+        (ReplyMode::Highlight, Some(nick)) => {
+            (target, Kind::Privmsg, format!("{}: {}", nick, msg))
+        }
+        _ => (target, Kind::Privmsg, msg.to_owned()),
+    }
+}
 
-    if msg_bytes + fix_bytes <= MESSAGE_BYTES_LIMIT {
-        trace!(log, "Message does not exceed limit: {}", msg);
-        send(&("\u{200B}".to_owned() + msg))?;
+fn send_reply(
+    cfg: &ServerCfg,
+    log: &Logger,
+    target: &str,
+    kind: Kind,
+    msg: String,
+    priority: Priority,
+    on_sent: Option<queue::OnSent>,
+) -> Result<()> {
+    let msg = if plain_text(cfg, target) {
+        format::strip(&msg)
     } else {
-        let mut count = 0;
-        let mut unescaped_controls = [false, false, false, false, false, false, false];
-        let mut color_code = String::with_capacity(5);
-        let mut current_msg = String::with_capacity(MESSAGE_BYTES_LIMIT - fix_bytes);
-        current_msg.push_str("\u{200B}");
-        let mut graphemes = UnicodeSegmentation::graphemes(msg, true).peekable();
-        // We don't use a for loop because we need to mutably access graphemes below
-        loop {
-            if let Some(next) = graphemes.next() {
-                // For magic values see https://stackoverflow.com/questions/1391610/embed-
-                //mirc-color-codes-into-a-c-sharp-literal/13382032#13382032
-                if next == "\x02" {
-                    if unescaped_controls[0] {
-                        count -= 1;
-                        unescaped_controls[0] = false;
-                    } else {
-                        count += 1;
-                        unescaped_controls[0] = true;
-                    }
-                } else if next == "\x03" {
-                    if unescaped_controls[1] {
-                        count -= 1 + color_code.len();
-                        color_code.clear();
-                        unescaped_controls[1] = false;
-                    } else {
-                        // worst case: \x0315,15
-                        let first = graphemes.next().unwrap();
-                        if *graphemes.peek().unwrap() == "," {
-                            // \x031,1
-                            let _ = graphemes.next().unwrap();
-                            let second = graphemes.next().unwrap();
-                            if graphemes.peek().unwrap().parse::<usize>().is_ok() {
-                                // \x031,15
-                                let third = graphemes.next().unwrap();
-                                count += 6;
-                                color_code.push_str(first);
-                                color_code.push_str(",");
-                                color_code.push_str(second);
-                                color_code.push_str(third);
-                            } else {
-                                // \x031,1
-                                count += 5;
-                                color_code.push_str(first);
-                                color_code.push_str(",");
-                                color_code.push_str(second);
-                            }
-                        } else if graphemes.peek().unwrap().parse::<usize>().is_ok() {
-                            // \x0315
-                            let second = graphemes.next().unwrap();
-                            if *graphemes.peek().unwrap() == "," {
-                                // \x0315,1
-                                let _ = graphemes.next().unwrap();
-                                let third = graphemes.next().unwrap();
-                                if graphemes.peek().unwrap().parse::<usize>().is_ok() {
-                                    // \x0315,15
-                                    count += 7;
-                                    let fourth = graphemes.next().unwrap();
-                                    color_code.push_str(first);
-                                    color_code.push_str(second);
-                                    color_code.push_str(",");
-                                    color_code.push_str(third);
-                                    color_code.push_str(fourth);
-                                } else {
-                                    // \x0315,1
-                                    count += 6;
-                                    color_code.push_str(first);
-                                    color_code.push_str(second);
-                                    color_code.push_str(",");
-                                    color_code.push_str(third);
-                                }
-                            } else {
-                                // \x0315
-                                let second = graphemes.next().unwrap();
-                                count += 4;
-                                color_code.push_str(first);
-                                color_code.push_str(second);
-                            }
-                        } else {
-                            // \x031
-                            count += 3;
-                            color_code.push_str(first);
-                        }
-                        unescaped_controls[1] = true;
-                    }
-                } else if next == "\x09" {
-                    if unescaped_controls[2] {
-                        count -= 1;
-                        unescaped_controls[2] = false;
-                    } else {
-                        count += 1;
-                        unescaped_controls[2] = true;
-                    }
-                } else if next == "\x13" {
-                    if unescaped_controls[3] {
-                        count -= 1;
-                        unescaped_controls[3] = false;
-                    } else {
-                        count += 1;
-                        unescaped_controls[3] = true;
-                    }
-                } else if next == "\x15" {
-                    if unescaped_controls[4] {
-                        count -= 1;
-                        unescaped_controls[4] = false;
-                    } else {
-                        count += 1;
-                        unescaped_controls[4] = true;
-                    }
-                } else if next == "\x1f" {
-                    if unescaped_controls[5] {
-                        count -= 1;
-                        unescaped_controls[5] = false;
-                    } else {
-                        count += 1;
-                        unescaped_controls[5] = true;
-                    }
-                } else if next == "\x16" {
-                    if unescaped_controls[6] {
-                        count -= 1;
-                        unescaped_controls[6] = false;
+        msg
+    };
+    let (mut lines, fix_bytes) = segment_lines(cfg, log, target, &msg);
+
+    // Replies that are too long for the channel are pasted, and summarized by their first line
+    let paste_after = cfg.channels
+        .iter()
+        .find(|c| c.name == target)
+        .and_then(|c| c.paste_after_lines)
+        .or(cfg.paste_after_lines);
+    if let Some(paste_after) = paste_after {
+        if lines.len() > paste_after && paste::enabled(cfg) {
+            match paste::upload(cfg, log, &format::strip(&msg)) {
+                Ok(url) => {
+                    let link = format!(" (+{} more lines: {})", lines.len() - 1, url);
+                    lines.truncate(1);
+                    if lines[0].len() + link.len() > MESSAGE_BYTES_LIMIT - fix_bytes {
+                        lines.push(link.trim().to_owned());
                     } else {
-                        count += 1;
-                        unescaped_controls[6] = true;
+                        lines[0].push_str(&link);
                     }
                 }
+                Err(e) => warn!(log, "Failed to paste reply: {:?}", e),
+            }
+        }
+    }
 
-                let len = next.bytes().len();
-                if count + len > MESSAGE_BYTES_LIMIT - fix_bytes {
-                    let if_any_unescaped_push = |out: &mut String, new_line| {
-                        if unescaped_controls[0] {
-                            out.push_str("\x02");
-                        }
-                        if unescaped_controls[1] {
-                            out.push_str("\x03");
-                            if new_line {
-                                out.push_str(&color_code);
-                            }
-                        }
-                        if unescaped_controls[2] {
-                            out.push_str("\x09");
-                        }
-                        if unescaped_controls[3] {
-                            out.push_str("\x13");
-                        }
-                        if unescaped_controls[4] {
-                            out.push_str("\x15");
-                        }
-                        if unescaped_controls[5] {
-                            out.push_str("\x1f");
-                        }
-                        if unescaped_controls[6] {
-                            out.push_str("\x16");
-                        }
-                    };
+    let max_lines = cfg.channels
+        .iter()
+        .find(|c| c.name == target)
+        .and_then(|c| c.max_lines)
+        .unwrap_or(usize::max_value());
+    let overflow = if lines.len() > max_lines {
+        lines.split_off(max_lines.max(1))
+    } else {
+        Vec::new()
+    };
+    send_lines(cfg, log, target, kind, lines, overflow.into(), priority, on_sent);
+    Ok(())
+}
 
-                    if_any_unescaped_push(&mut current_msg, false);
-                    trace!(log, "Sending {} cut msg: {:?}", target, &current_msg);
+// Splits `msg` into the lines sent to `target`, returns them and the bytes the server adds
+fn segment_lines(
+    cfg: &ServerCfg,
+    log: &Logger,
+    target: &str,
+    msg: &str,
+) -> (Vec<String>, usize) {
+    // :<hostname> PRIVMSG <target> :\u{200B}<message>, NOTICE is shorter
+    let fix_bytes = 1 + HOSTNAMES.read().get(&cfg.address).unwrap().bytes().len() + 9
+        + target.bytes().len() + 3 + "\u{200B}".len();
+    let marker = if cfg.continuation_marker.unwrap_or(false) {
+        CONTINUATION_MARKER
+    } else {
+        ""
+    };
+    let budget = MESSAGE_BYTES_LIMIT - fix_bytes - RESERVED_CONTROL_BYTES - marker.len();
+    trace!(log, "Msg bytes: {}; Fix bytes: {}", msg.len(), fix_bytes);
 
-                    send(&current_msg)?;
-                    count = 0;
-                    current_msg.clear();
-                    current_msg.push_str("\u{200B}");
-                    if_any_unescaped_push(&mut current_msg, true);
-                }
-                count += len;
-                current_msg.push_str(next);
+    let mut lines = segment::split(msg, budget);
+    let mut style = format::Style::default();
+    for line in &mut lines {
+        let codes = style.codes_before(line);
+        style = format::end_style(style, line);
+        line.insert_str(0, &codes);
+    }
+    // Lines that were cut, instead of ending in a newline, get the marker
+    if !marker.is_empty() {
+        let mut paragraphs = msg.split('\n').filter(|p| !p.trim().is_empty()).map(|p| {
+            segment::split(p, budget).len()
+        });
+        let mut left = paragraphs.next().unwrap_or(0);
+        for line in &mut lines {
+            left -= 1;
+            if left == 0 {
+                left = paragraphs.next().unwrap_or(0);
             } else {
-                if !current_msg.is_empty() {
-                    send(&current_msg)?;
-                }
-                break;
+                line.push_str(marker);
             }
         }
     }
-    Ok(())
+    (lines, fix_bytes)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_lines(
+    cfg: &ServerCfg,
+    log: &Logger,
+    target: &str,
+    kind: Kind,
+    mut lines: Vec<String>,
+    mut overflow: VecDeque<String>,
+    priority: Priority,
+    on_sent: Option<queue::OnSent>,
+) {
+    if !overflow.is_empty() {
+        let hint = format!(
+            " (+{} more, see `{}more`)",
+            overflow.len(),
+            command_prefixes(cfg, target)[0]
+        );
+        let last = lines.pop().unwrap();
+        if last.len() + hint.len() <= MESSAGE_BYTES_LIMIT - RESERVED_CONTROL_BYTES {
+            lines.push(last + &hint);
+        } else {
+            overflow.push_front(last);
+            lines.push(hint.trim().to_owned());
+        }
+        trace!(log, "Keeping {} lines for {}", overflow.len(), target);
+    }
+    let mut hm = OVERFLOW.lock();
+    if overflow.is_empty() {
+        hm.remove(&(cfg.address.clone(), target.to_owned()));
+    } else {
+        hm.insert((cfg.address.clone(), target.to_owned()), (kind, overflow));
+    }
+    drop(hm);
+
+    trace!(log, "Sending {} lines: {:?}", target, &lines);
+    queue::push(
+        cfg,
+        log,
+        target,
+        kind,
+        lines
+            .into_iter()
+            .map(|line| "\u{200B}".to_owned() + &line)
+            .collect(),
+        priority,
+        on_sent,
+    );
+}
+
+// Sends the lines that did not fit into the last reply to `target`
+fn send_more(cfg: &ServerCfg, log: &Logger, target: &str, priority: Priority) -> bool {
+    let overflow = OVERFLOW
+        .lock()
+        .remove(&(cfg.address.clone(), target.to_owned()));
+    if let Some((kind, mut overflow)) = overflow {
+        let max_lines = cfg.channels
+            .iter()
+            .find(|c| c.name == target)
+            .and_then(|c| c.max_lines)
+            .unwrap_or(usize::max_value())
+            .max(1);
+        let rest = overflow.split_off(max_lines.min(overflow.len()));
+        send_lines(
+            cfg,
+            log,
+            target,
+            kind,
+            overflow.into_iter().collect(),
+            rest,
+            priority,
+            None,
+        );
+        true
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_command(name: &str) -> bool {
+        COMMANDS.contains(&name)
+    }
+
+    #[test]
+    fn pipeline_steps_split() {
+        assert_eq!(
+            pipeline_steps("g rust borrow checker | tell bob", is_command),
+            Some(vec!["g rust borrow checker", "tell bob"])
+        );
+        assert_eq!(
+            pipeline_steps("wa 1+1 | choose a | tell #chan", is_command),
+            Some(vec!["wa 1+1", "choose a", "tell #chan"])
+        );
+    }
+
+    #[test]
+    fn pipeline_steps_arguments() {
+        assert_eq!(pipeline_steps("weather Tokyo", is_command), None);
+        assert_eq!(pipeline_steps("tell bob use a | b", is_command), None);
+        assert_eq!(pipeline_steps("choose \"a | b\" c", is_command), None);
+        assert_eq!(pipeline_steps("g a | tell bob | b", is_command), None);
+        assert_eq!(pipeline_steps("g a |  ", is_command), None);
+        // Without spaces around it, `|` is no pipe
+        assert_eq!(pipeline_steps("g a|tell bob", is_command), None);
+    }
+
+    fn is_parabot(nick: &str) -> bool {
+        irc_lowercase(nick) == "parabot"
+    }
+
+    #[test]
+    fn strip_command_prefixes() {
+        assert_eq!(strip_command(&["."], is_parabot, ".g rust"), Some("g rust"));
+        assert_eq!(strip_command(&[">", ">>"], is_parabot, ">>tells"), Some("tells"));
+        assert_eq!(strip_command(&["."], is_parabot, "."), None);
+        assert_eq!(strip_command(&["."], is_parabot, ".  "), None);
+        assert_eq!(strip_command(&["."], is_parabot, "g rust"), None);
+    }
+
+    #[test]
+    fn strip_command_mentions() {
+        assert_eq!(strip_command(&["."], is_parabot, "parabot: g rust"), Some("g rust"));
+        assert_eq!(strip_command(&["."], is_parabot, "ParaBot, .g rust"), Some("g rust"));
+        assert_eq!(strip_command(&["."], is_parabot, "parabot:weather"), Some("weather"));
+        assert_eq!(strip_command(&["."], is_parabot, " parabot "), Some(""));
+        assert_eq!(strip_command(&["."], is_parabot, "parabot:"), Some(""));
+        assert_eq!(strip_command(&["."], is_parabot, "parabot is great"), None);
+        assert_eq!(strip_command(&["."], is_parabot, "otherbot: g rust"), None);
+        assert_eq!(strip_command(&["."], is_parabot, "see: parabot"), None);
+    }
+
+    #[test]
+    fn tells_delivered_before_commands() {
+        assert!(delivers_tells(None));
+        assert!(delivers_tells(Some("")));
+        assert!(delivers_tells(Some("weather Tokyo")));
+        assert!(delivers_tells(Some("tell bob hi")));
+        // Managing tells must not deliver them first
+        assert!(!delivers_tells(Some("tells")));
+        assert!(!delivers_tells(Some("tells sent")));
+        assert!(!delivers_tells(Some("tell snooze 2h")));
+        assert!(!delivers_tells(Some("tell  cancel #3")));
+        assert!(!delivers_tells(Some("tell edit 3 hi")));
+        let mention = strip_command(&["."], is_parabot, "parabot: tell snooze");
+        assert!(!delivers_tells(mention));
+    }
+
+    #[test]
+    fn split_command_names() {
+        assert_eq!(split_command("g rust  borrow"), ("g", "rust  borrow"));
+        assert_eq!(split_command("tells"), ("tells", ""));
+        assert_eq!(split_command("weather  "), ("weather", ""));
+        // A mention like `parabot: good bot` is not the `g` command
+        assert_eq!(split_command("good bot").0, "good");
+        assert_eq!(split_command("tellme").0, "tellme");
+    }
 }
@@ -0,0 +1,199 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+// Uploads long replies, either to the built-in paste server or to a configured endpoint
+
+use parking_lot::{Mutex, RwLock};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use reqwest::{Client, RedirectPolicy};
+use reqwest::header::LOCATION;
+use slog::Logger;
+use threadpool::ThreadPool;
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use config::ServerCfg;
+use errors::*;
+
+const DEFAULT_MAX_AGE: u64 = 24 * 60 * 60;
+const DEFAULT_MAX_PASTES: usize = 1000;
+const ID_LENGTH: usize = 8;
+// Connections served at once, and waiting to be served; any more are dropped
+const SERVE_THREADS: usize = 4;
+const MAX_WAITING: usize = 16;
+const TIMEOUT: Duration = Duration::from_secs(10);
+// `GET /<id> HTTP/1.1` is far shorter
+const MAX_REQUEST_LINE: u64 = 1024;
+
+lazy_static!{
+    // paste_listen -> id -> (creation time, text), servers may share a listener
+    static ref PASTES: RwLock<HashMap<String, HashMap<String, (Instant, String)>>> = {
+        RwLock::new(HashMap::new())
+    };
+    // Addresses that are being listened on
+    static ref LISTENING: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+/// Whether a paste backend is configured
+pub fn enabled(cfg: &ServerCfg) -> bool {
+    cfg.paste_endpoint.is_some() || cfg.paste_listen.is_some()
+}
+
+/// Uploads `text`, returns its URL
+pub fn upload(cfg: &ServerCfg, log: &Logger, text: &str) -> Result<String> {
+    if let Some(ref endpoint) = cfg.paste_endpoint {
+        // A redirect to the paste is its URL, so don't follow it
+        let client = Client::builder()
+            .redirect(RedirectPolicy::none())
+            .build()?;
+        let req = client.post(endpoint);
+        let req = if let Some(ref field) = cfg.paste_form_field {
+            req.form(&[(&**field, text)])
+        } else {
+            req.body(text.to_owned())
+        };
+        let mut resp = req.send()?.error_for_status()?;
+        // Pastebins either redirect to the paste, or reply with its URL
+        let url = if let Some(url) = resp.headers()
+            .get(LOCATION)
+            .and_then(|l| l.to_str().ok())
+        {
+            url.to_owned()
+        } else {
+            resp.text()?.trim().to_owned()
+        };
+        debug!(log, "Uploaded paste to {}", url);
+        Ok(url)
+    } else {
+        let id = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(ID_LENGTH)
+            .collect::<String>();
+        let max_pastes = cfg.paste_max_count.unwrap_or(DEFAULT_MAX_PASTES);
+        let mut hm = PASTES.write();
+        let pastes = hm.entry(cfg.paste_listen.clone().unwrap())
+            .or_insert_with(HashMap::new);
+        expire(cfg, pastes);
+        if pastes.len() >= max_pastes {
+            let oldest = pastes
+                .iter()
+                .min_by_key(|&(_, &(created, _))| created)
+                .map(|(id, _)| id.clone())
+                .unwrap();
+            pastes.remove(&oldest);
+        }
+        pastes.insert(id.clone(), (Instant::now(), text.to_owned()));
+        debug!(log, "Stored paste {}", id);
+
+        let base = cfg.paste_base_url
+            .clone()
+            .unwrap_or_else(|| format!("http://{}/", cfg.paste_listen.as_ref().unwrap()));
+        if base.ends_with('/') {
+            Ok(base + &id)
+        } else {
+            Ok(base + "/" + &id)
+        }
+    }
+}
+
+fn expire(cfg: &ServerCfg, pastes: &mut HashMap<String, (Instant, String)>) {
+    let max_age = Duration::from_secs(cfg.paste_max_age.unwrap_or(DEFAULT_MAX_AGE));
+    pastes.retain(|_, &mut (created, _)| created.elapsed() < max_age);
+}
+
+/// Serves the pastes of the server over HTTP, if `paste_listen` is set; never returns otherwise
+pub fn run(cfg: &ServerCfg, log: &Logger) -> Result<()> {
+    let addr = if let Some(ref addr) = cfg.paste_listen {
+        addr
+    } else {
+        return Ok(());
+    };
+    // Servers sharing the address are served by the first one's listener
+    if !LISTENING.lock().insert(addr.clone()) {
+        info!(log, "Pastes on {} are already served", addr);
+        return Ok(());
+    }
+    let listener = TcpListener::bind(&**addr)?;
+    info!(log, "Serving pastes on {}", addr);
+
+    let pool = ThreadPool::new(SERVE_THREADS);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!(log, "Failed to accept paste connection: {:?}", e);
+                continue;
+            }
+        };
+        if pool.queued_count() >= MAX_WAITING {
+            debug!(log, "Too many paste connections, dropping one");
+            continue;
+        }
+        let cfg = cfg.clone();
+        let log = log.clone();
+        pool.execute(move || {
+            if let Err(e) = serve(&cfg, &log, stream) {
+                debug!(log, "Failed to serve paste: {:?}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn serve(cfg: &ServerCfg, log: &Logger, mut stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    // Only the request line is of interest, e.g. `GET /abcd1234 HTTP/1.1`
+    let mut request = String::new();
+    BufReader::new((&stream).take(MAX_REQUEST_LINE)).read_line(&mut request)?;
+    trace!(log, "Paste request: {:?}", request.trim());
+    let mut split = request.split_whitespace();
+    let (method, path) = (split.next().unwrap_or(""), split.next().unwrap_or(""));
+
+    let paste = if method == "GET" || method == "HEAD" {
+        let mut hm = PASTES.write();
+        hm.get_mut(cfg.paste_listen.as_ref().unwrap())
+            .and_then(|pastes| {
+                expire(cfg, pastes);
+                pastes
+                    .get(path.trim_start_matches('/'))
+                    .map(|&(_, ref text)| text.clone())
+            })
+    } else {
+        None
+    };
+
+    let (status, body) = match paste {
+        Some(text) => ("200 OK", text),
+        None => ("404 Not Found", "Not found, it may have expired.\n".to_owned()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(body.as_bytes())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use chrono_tz::Tz;
+use diesel;
+use diesel::prelude::*;
+use parking_lot::RwLock;
+use slog::Logger;
+
+use std::collections::HashMap;
+
+use config::{Config, ServerCfg, Units};
+use errors::*;
+use models;
+use schema;
+use schema::user_preferences::dsl;
+
+// Preferences users can set, and a description of their values
+const KNOWN: &[(&str, &str)] = &[
+    ("plain", "`on` to receive replies without formatting, or `off`"),
+    ("receipts", "`on` to be told when your tells were delivered, or `off`"),
+    ("timezone", "e.g. `Europe/Berlin`, to show times in your timezone"),
+    ("units", "`metric`, `imperial`, `uk` or `si`, for weather information"),
+];
+
+lazy_static!{
+    // (server, nick, name) -> value, nicks are irc_lowercased
+    static ref PREFERENCES: RwLock<HashMap<(String, String, String), String>> = {
+        RwLock::new(HashMap::new())
+    };
+}
+
+// Read DB to get init values
+pub fn init(cfg: &Config, log: &Logger) -> Result<()> {
+    let mut hm = PREFERENCES.write();
+    for srv in &cfg.servers {
+        let prefs = super::with_database(srv, |db| {
+            Ok(dsl::user_preferences
+                .filter(dsl::server.eq(&srv.address))
+                .load::<models::UserPreference>(db)?)
+        })?;
+
+        info!(log, "User preferences: {:?}", &prefs);
+        for p in prefs {
+            hm.insert((p.server, p.nick, p.name), p.value);
+        }
+    }
+    hm.shrink_to_fit();
+    Ok(())
+}
+
+pub fn get(cfg: &ServerCfg, nick: &str, name: &str) -> Option<String> {
+    PREFERENCES
+        .read()
+        .get(&(
+            cfg.address.clone(),
+            super::irc_lowercase(nick),
+            name.to_owned(),
+        ))
+        .cloned()
+}
+
+/// Whether `nick` turned on the preference `name`
+pub fn enabled(cfg: &ServerCfg, nick: &str, name: &str) -> bool {
+    get(cfg, nick, name).map(|v| v == "on").unwrap_or(false)
+}
+
+/// Whether `nick` opted out of formatted replies
+pub fn plain_text(cfg: &ServerCfg, nick: &str) -> bool {
+    enabled(cfg, nick, "plain")
+}
+
+// Returns the normalized value, or why it is invalid
+fn validate(name: &str, value: &str) -> ::std::result::Result<String, String> {
+    match name {
+        "plain" | "receipts" => match &*value.to_lowercase() {
+            "on" | "yes" | "true" => Ok("on".to_owned()),
+            "off" | "no" | "false" => Ok("off".to_owned()),
+            _ => Err("Expected `on` or `off`.".to_owned()),
+        },
+        "timezone" => value
+            .parse::<Tz>()
+            .map(|tz| tz.name().to_owned())
+            .map_err(|_| "Unknown timezone, try e.g. `Europe/Berlin`.".to_owned()),
+        "units" => Units::parse(value)
+            .map(|u| u.name().to_owned())
+            .ok_or_else(|| "Expected `metric`, `imperial`, `uk` or `si`.".to_owned()),
+        _ => unreachable!(),
+    }
+}
+
+pub fn handle(
+    cfg: &ServerCfg,
+    log: &Logger,
+    nick: &str,
+    args: &str,
+    prefix: &str,
+) -> Result<String> {
+    let lower_nick = super::irc_lowercase(nick);
+    let mut split = args.splitn(2, ' ');
+    let name = split.next().unwrap();
+    let value = split.next().unwrap_or("").trim();
+
+    if name.is_empty() {
+        let hm = PREFERENCES.read();
+        let mut list = hm.iter()
+            .filter(|&(&(ref server, ref n, _), _)| *server == cfg.address && *n == lower_nick)
+            .map(|(&(_, _, ref name), value)| format!("{} = {}", name, value))
+            .collect::<Vec<_>>();
+        return if list.is_empty() {
+            Ok(format!(
+                "{}: You have not set any preferences, see `{}help set`.",
+                nick, prefix
+            ))
+        } else {
+            list.sort();
+            Ok(format!("{}: {}", nick, list.join("; ")))
+        };
+    }
+    if !KNOWN.iter().any(|&(n, _)| n == name) {
+        return Ok(format!(
+            "{}: Unknown preference `{}`, see `{}help set`.",
+            nick, name, prefix
+        ));
+    }
+
+    let key = (cfg.address.clone(), lower_nick.clone(), name.to_owned());
+    if value.is_empty() {
+        PREFERENCES.write().remove(&key);
+        super::with_database(cfg, |db| {
+            diesel::delete(
+                dsl::user_preferences
+                    .filter(dsl::server.eq(&cfg.address))
+                    .filter(dsl::nick.eq(&lower_nick))
+                    .filter(dsl::name.eq(name)),
+            ).execute(db)?;
+            Ok(())
+        })?;
+        return Ok(format!("{}: Reset `{}` to its default.", nick, name));
+    }
+
+    let value = match validate(name, value) {
+        Ok(value) => value,
+        Err(e) => {
+            trace!(log, "Invalid preference {} = {:?}", name, value);
+            return Ok(format!("{}: {}", nick, e));
+        }
+    };
+    let new = models::NewUserPreference {
+        server: &cfg.address,
+        nick: &lower_nick,
+        name: name,
+        value: &value,
+    };
+    super::with_database(cfg, |db| {
+        diesel::replace_into(schema::user_preferences::table)
+            .values(&new)
+            .execute(db)?;
+        Ok(())
+    })?;
+    PREFERENCES.write().insert(key, value.clone());
+
+    Ok(format!("{}: Set `{}` to `{}`.", nick, name, value))
+}
+
+/// Descriptions of all preferences, for `.help set`
+pub fn describe() -> String {
+    KNOWN
+        .iter()
+        .map(|&(name, desc)| format!("`{}`: {}", name, desc))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
@@ -0,0 +1,272 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use irc::client::prelude::*;
+use parking_lot::{Condvar, Mutex, RwLock};
+use slog::Logger;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use config::ServerCfg;
+use errors::*;
+
+// The same defaults the irc crate uses for its flood control
+const DEFAULT_MAX_BURST_MESSAGES: u32 = 15;
+const DEFAULT_BURST_WINDOW_LENGTH: u32 = 8;
+const DEFAULT_MAX_AGE: u32 = 30;
+// Bulk output waits behind replies, so it may get older
+const DEFAULT_BULK_MAX_AGE: u32 = 300;
+
+/// Interactive replies are sent before any bulk output, e.g. tell delivery or alerts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Interactive,
+    Bulk,
+}
+
+/// Called once a reply was sent, with false if it was dropped instead
+pub type OnSent = Box<FnMut(bool) + Send>;
+
+/// The command a line is sent with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Privmsg,
+    Notice,
+}
+
+struct Line {
+    kind: Kind,
+    text: String,
+    queued: Instant,
+    // Lines of the same reply are dropped together
+    reply: usize,
+    // Set on the last line of a reply
+    on_sent: Option<OnSent>,
+}
+
+// One FIFO per target, the targets take turns
+#[derive(Default)]
+struct Targets(VecDeque<(String, VecDeque<Line>)>);
+
+impl Targets {
+    fn push(&mut self, target: &str, line: Line) {
+        if let Some(&mut (_, ref mut lines)) =
+            self.0.iter_mut().find(|&&mut (ref t, _)| t == target)
+        {
+            lines.push_back(line);
+            return;
+        }
+        let mut lines = VecDeque::new();
+        lines.push_back(line);
+        self.0.push_back((target.to_owned(), lines));
+    }
+
+    fn pop(&mut self) -> Option<(String, Line)> {
+        let (target, mut lines) = self.0.pop_front()?;
+        let line = lines.pop_front()?;
+        let ret = (target.clone(), line);
+        if !lines.is_empty() {
+            self.0.push_back((target, lines));
+        }
+        Some(ret)
+    }
+
+    // Drops the queued lines of `reply` to `target`, returns them
+    fn drop_reply(&mut self, target: &str, reply: usize) -> Vec<Line> {
+        let pos = if let Some(pos) = self.0.iter().position(|&(ref t, _)| t == target) {
+            pos
+        } else {
+            return Vec::new();
+        };
+        let (dropped, kept) = self.0[pos]
+            .1
+            .drain(..)
+            .partition::<Vec<_>, _>(|l| l.reply == reply);
+        self.0[pos].1.extend(kept);
+        if self.0[pos].1.is_empty() {
+            self.0.remove(pos);
+        }
+        dropped
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|&(_, ref lines)| lines.len()).sum()
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    interactive: Targets,
+    bulk: Targets,
+}
+
+lazy_static!{
+    static ref NEXT_REPLY: AtomicUsize = AtomicUsize::new(0);
+    static ref QUEUES: RwLock<HashMap<String, Arc<(Mutex<Queue>, Condvar)>>> = {
+        RwLock::new(HashMap::new())
+    };
+}
+
+fn queue_of(cfg: &ServerCfg) -> Arc<(Mutex<Queue>, Condvar)> {
+    if let Some(q) = QUEUES.read().get(&cfg.address) {
+        return q.clone();
+    }
+    QUEUES
+        .write()
+        .entry(cfg.address.clone())
+        .or_insert_with(|| Arc::new((Mutex::new(Queue::default()), Condvar::new())))
+        .clone()
+}
+
+/// Queues the lines of a reply, `on_sent` is called once the last one was sent
+pub fn push(
+    cfg: &ServerCfg,
+    log: &Logger,
+    target: &str,
+    kind: Kind,
+    lines: Vec<String>,
+    priority: Priority,
+    mut on_sent: Option<OnSent>,
+) {
+    let q = queue_of(cfg);
+    let mut queue = q.0.lock();
+    let reply = NEXT_REPLY.fetch_add(1, Ordering::Relaxed);
+    let queued = Instant::now();
+    let num = lines.len();
+    for (i, text) in lines.into_iter().enumerate() {
+        let line = Line {
+            kind,
+            text,
+            queued,
+            reply,
+            on_sent: if i + 1 == num { on_sent.take() } else { None },
+        };
+        match priority {
+            Priority::Interactive => queue.interactive.push(target, line),
+            Priority::Bulk => queue.bulk.push(target, line),
+        }
+    }
+    trace!(
+        log,
+        "Queued {} line(s) for {}, depth: {} interactive, {} bulk",
+        num,
+        target,
+        queue.interactive.len(),
+        queue.bulk.len()
+    );
+    q.1.notify_one();
+    drop(queue);
+    // Nothing to send, e.g. for an empty reply
+    if let Some(mut on_sent) = on_sent {
+        on_sent(true);
+    }
+}
+
+/// Number of queued interactive and bulk lines
+pub fn depth(cfg: &ServerCfg) -> (usize, usize) {
+    let q = queue_of(cfg);
+    let queue = q.0.lock();
+    (queue.interactive.len(), queue.bulk.len())
+}
+
+/// Sends queued lines to the server, never returns unless sending fails
+pub fn run(cfg: &ServerCfg, srv: &IrcClient, log: &Logger) -> Result<()> {
+    let max_burst = cfg.max_burst_messages
+        .unwrap_or(DEFAULT_MAX_BURST_MESSAGES) as usize;
+    let window = Duration::from_secs(u64::from(
+        cfg.burst_window_length
+            .unwrap_or(DEFAULT_BURST_WINDOW_LENGTH),
+    ));
+    let max_age = Duration::from_secs(u64::from(cfg.queue_max_age.unwrap_or(DEFAULT_MAX_AGE)));
+    let bulk_max_age = Duration::from_secs(u64::from(
+        cfg.bulk_queue_max_age.unwrap_or(DEFAULT_BULK_MAX_AGE),
+    ));
+    let mut sent: VecDeque<Instant> = VecDeque::with_capacity(max_burst);
+
+    let q = queue_of(cfg);
+    loop {
+        // Wait until the burst window allows another message
+        let now = Instant::now();
+        while sent
+            .front()
+            .map(|t| now.duration_since(*t) > window)
+            .unwrap_or(false)
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= max_burst {
+            thread::sleep(window - now.duration_since(*sent.front().unwrap()));
+            continue;
+        }
+
+        let mut dropped = Vec::new();
+        let next = {
+            let mut queue = q.0.lock();
+            loop {
+                let (target, line, stale) = if let Some((target, line)) = queue.interactive.pop()
+                {
+                    let stale = line.queued.elapsed() > max_age;
+                    (target, line, stale)
+                } else if let Some((target, line)) = queue.bulk.pop() {
+                    let stale = line.queued.elapsed() > bulk_max_age;
+                    (target, line, stale)
+                } else if dropped.is_empty() {
+                    q.1.wait(&mut queue);
+                    continue;
+                } else {
+                    break None;
+                };
+                if !stale {
+                    break Some((target, line));
+                }
+
+                // Rather drop the whole reply than send it truncated
+                let mut lines = queue.interactive.drop_reply(&target, line.reply);
+                lines.extend(queue.bulk.drop_reply(&target, line.reply));
+                debug!(
+                    log,
+                    "Dropping stale reply for {}, {} line(s): {:?}…",
+                    target,
+                    lines.len() + 1,
+                    line.text
+                );
+                dropped.push(line);
+                dropped.extend(lines);
+            }
+        };
+        // Called without holding the queue, they may queue replies themselves
+        for mut on_sent in dropped.into_iter().filter_map(|l| l.on_sent) {
+            on_sent(false);
+        }
+
+        if let Some((target, line)) = next {
+            trace!(log, "Sending {} queued line: {:?}", target, line.text);
+            match line.kind {
+                Kind::Privmsg => srv.send_privmsg(&target, &line.text)?,
+                Kind::Notice => srv.send_notice(&target, &line.text)?,
+            }
+            sent.push_back(Instant::now());
+            if let Some(mut on_sent) = line.on_sent {
+                on_sent(true);
+            }
+        }
+    }
+}
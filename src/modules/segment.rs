@@ -0,0 +1,170 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+// Lines are preferably cut after these, if there is no whitespace nearby
+const PUNCTUATION: &[char] = &[',', ';', ':', '.', '!', '?', ')', ']', '-', '/', '…'];
+
+lazy_static!{
+    static ref URL_REGEX: Regex = Regex::new("(?:https?|ftp)://\\S+").unwrap();
+    // `\x03[fg[,bg]]` and `\x04[RRGGBB[,RRGGBB]]`, see format
+    static ref COLOR_REGEX: Regex = Regex::new(
+        "\x03(?:\\d{1,2}(?:,\\d{1,2})?)?|\x04(?:[0-9a-fA-F]{6}(?:,[0-9a-fA-F]{6})?)?"
+    ).unwrap();
+}
+
+/// Splits `msg` into lines of at most `budget` bytes, at newlines and preferably at whitespace
+/// or punctuation. URLs are only cut if they don't fit into a line on their own.
+pub fn split(msg: &str, budget: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in msg.split('\n') {
+        let mut rest = paragraph.trim();
+        while rest.len() > budget {
+            let cut = cut_position(rest, budget);
+            lines.push(rest[..cut].trim_end().to_owned());
+            rest = rest[cut..].trim_start();
+        }
+        if !rest.is_empty() {
+            lines.push(rest.to_owned());
+        }
+    }
+    lines
+}
+
+// The byte index to cut `s` at, so that the first part is not longer than `budget`
+fn cut_position(s: &str, budget: usize) -> usize {
+    let urls = URL_REGEX
+        .find_iter(s)
+        .map(|m| (m.start(), m.end()))
+        .collect::<Vec<_>>();
+    let in_url = |i: usize| urls.iter().any(|&(start, end)| start < i && i < end);
+    let colors = COLOR_REGEX
+        .find_iter(s)
+        .map(|m| (m.start(), m.end()))
+        .collect::<Vec<_>>();
+    let in_color = |i: usize| colors.iter().any(|&(start, end)| start < i && i < end);
+
+    let (mut space, mut punctuation, mut any, mut hard) = (None, None, None, None);
+    for (i, g) in s.grapheme_indices(true) {
+        let end = i + g.len();
+        if end > budget {
+            break;
+        }
+        // Cutting a color code would leak its digits as text
+        if in_color(end) {
+            continue;
+        }
+        hard = Some(end);
+        if in_url(end) {
+            continue;
+        }
+        any = Some(end);
+        if g.chars().all(char::is_whitespace) {
+            space = Some(end);
+        } else if g.chars().all(|c| PUNCTUATION.contains(&c)) {
+            punctuation = Some(end);
+        }
+    }
+
+    // Don't produce needlessly short lines by cutting at the first space
+    let long_enough = |i: &usize| *i > budget / 2;
+    space
+        .filter(&long_enough)
+        .or_else(|| punctuation.filter(&long_enough))
+        .or(space)
+        .or(punctuation)
+        .or(any)
+        .or(hard)
+        // A single grapheme that exceeds the budget, nothing to be done
+        .unwrap_or_else(|| {
+            s.grapheme_indices(true)
+                .nth(1)
+                .map(|(i, _)| i)
+                .unwrap_or_else(|| s.len())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short() {
+        assert_eq!(split("hello world", 20), vec!["hello world"]);
+        assert_eq!(split("a\n\nb", 20), vec!["a", "b"]);
+        assert!(split("  \n ", 20).is_empty());
+    }
+
+    #[test]
+    fn budget_edges() {
+        assert_eq!(split("abcde", 5), vec!["abcde"]);
+        assert_eq!(split("abcdef", 5), vec!["abcde", "f"]);
+        assert_eq!(split("ab cdef", 5), vec!["ab", "cdef"]);
+        for line in split("the quick brown fox jumps over the lazy dog", 7) {
+            assert!(line.len() <= 7, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn whitespace_and_punctuation() {
+        assert_eq!(split("hello world foo", 11), vec!["hello", "world foo"]);
+        assert_eq!(split("abcdefgh,ijkl", 10), vec!["abcdefgh,", "ijkl"]);
+    }
+
+    #[test]
+    fn multibyte() {
+        assert_eq!(split("äöü", 4), vec!["äö", "ü"]);
+        // A grapheme larger than the budget is kept whole
+        assert_eq!(split("e\u{301}", 1), vec!["e\u{301}"]);
+    }
+
+    #[test]
+    fn urls() {
+        // Kept whole if it fits into a line
+        assert_eq!(
+            split("aaaa https://example.com/x bb", 25),
+            vec!["aaaa", "https://example.com/x bb"]
+        );
+        // Otherwise cut
+        assert_eq!(
+            split("see https://example.com/abc", 20),
+            vec!["see", "https://example.com/", "abc"]
+        );
+    }
+
+    #[test]
+    fn colors() {
+        // Not between `\x03` and its digits
+        assert_eq!(
+            split("abcdefgh\x0304,05ijkl", 11),
+            vec!["abcdefgh", "\x0304,05ijkl"]
+        );
+        // Not after the comma of a background
+        assert_eq!(
+            split("aaaaaa\x0304,05bbbbbbbbbb", 12),
+            vec!["aaaaaa\x0304,05", "bbbbbbbbbb"]
+        );
+        assert_eq!(
+            split("abcd\x04FF0000,00FF00efgh", 16),
+            vec!["abcd", "\x04FF0000,00FF00ef", "gh"]
+        );
+        // A comma after a color is text
+        assert_eq!(split("abcde\x0304, fg", 9), vec!["abcde\x0304,", "fg"]);
+    }
+}
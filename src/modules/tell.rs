@@ -15,90 +15,170 @@
 // You should have received a copy of the GNU General Public License
 // along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
 
-use chrono::Utc;
+use chrono::{Duration, TimeZone, Utc};
+use chrono_tz::Tz;
 use diesel;
 use diesel::prelude::*;
 use irc::client::prelude::*;
-use parking_lot::Mutex;
+use diesel::sql_types::Bool;
+use diesel::sqlite::Sqlite;
+use parking_lot::{Mutex, RwLock};
 use slog::Logger;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use std::time::Duration as StdDuration;
 
 use config::{Config, ServerCfg};
 use errors::*;
 use models;
 use schema;
+use super::prefs;
+use super::queue::Priority;
 use schema::pending_tells::dsl;
 
+const DEFAULT_MAX_PENDING: usize = 20;
+
+type Filter = Box<BoxableExpression<dsl::pending_tells, Sqlite, SqlType = Bool>>;
+
+no_arg_sql_function!(
+    last_insert_rowid,
+    diesel::sql_types::Integer,
+    "The rowid of the last inserted row"
+);
+
 lazy_static!{
-    static ref PENDING_TELLS: Mutex<HashMap<String, Mutex<usize>>> = {
+    // server -> lowercased targets of the tells that can be delivered there
+    static ref PENDING: RwLock<HashMap<String, HashSet<String>>> = {
+        RwLock::new(HashMap::new())
+    };
+    // network name -> (server address, database), for `nick@network`
+    static ref NETWORKS: RwLock<HashMap<String, (String, String)>> = {
+        RwLock::new(HashMap::new())
+    };
+    // (database, id) of the tells that are queued, but not sent yet
+    static ref QUEUED: Mutex<HashSet<(String, i32)>> = Mutex::new(HashSet::new());
+    // (server, nick) -> source of the last tell delivered to nick, for `.reply`
+    static ref LAST_DELIVERED: Mutex<HashMap<(String, String), String>> = {
         Mutex::new(HashMap::new())
     };
 }
 
 // Read DB to get init values
 pub fn init(cfg: &Config, log: &Logger) -> Result<()> {
-    let mut hm = PENDING_TELLS.lock();
+    {
+        let mut networks = NETWORKS.write();
+        for srv in &cfg.servers {
+            networks.insert(
+                network(srv).to_lowercase(),
+                (srv.address.clone(), srv.database.clone()),
+            );
+        }
+    }
+    let mut pending = PENDING.write();
     for srv in &cfg.servers {
-        let tells = super::with_database(srv, |db| {
-            Ok(dsl::pending_tells
-                .filter(dsl::server_addr.eq(&srv.address))
-                .load::<models::PendingTell>(db)?)
-        })?;
+        let targets = load_targets(srv, &srv.address)?;
+        info!(log, "Pending tells on {}: {:?}", srv.address, &targets);
+        pending.insert(srv.address.clone(), targets);
+    }
+    pending.shrink_to_fit();
+    Ok(())
+}
 
-        info!(log, "Pending tells: {:?}", &tells);
-        hm.insert(srv.address.clone(), Mutex::new(tells.len()));
-        hm.shrink_to_fit();
+// The targets of the tells that can be delivered on the server with `address`
+fn load_targets(cfg: &ServerCfg, address: &str) -> Result<HashSet<String>> {
+    let targets = super::with_database(cfg, |db| {
+        Ok(dsl::pending_tells
+            .filter(to_here(address))
+            .select(dsl::target_nick)
+            .distinct()
+            .load::<String>(db)?)
+    })?;
+    Ok(targets.iter().map(|t| super::irc_lowercase(t)).collect())
+}
+
+// Reloads the targets after the tells changed. Tells from other networks may be delivered by
+// any server sharing the database, so theirs are reloaded too.
+fn resync(cfg: &ServerCfg) -> Result<()> {
+    let addresses = NETWORKS
+        .read()
+        .values()
+        .filter(|&&(_, ref database)| *database == cfg.database)
+        .map(|&(ref address, _)| address.clone())
+        .collect::<Vec<_>>();
+    // Held while loading, so concurrent changes can't leave an outdated set behind
+    let mut pending = PENDING.write();
+    for address in addresses {
+        let targets = load_targets(cfg, &address)?;
+        pending.insert(address, targets);
     }
     Ok(())
 }
 
+// The name of the server's network in `nick@network`
+fn network(cfg: &ServerCfg) -> &str {
+    cfg.network.as_ref().unwrap_or(&cfg.address)
+}
+
+// Tells that can be delivered on this server, i.e. those to it, and those from other networks,
+// which are delivered wherever their target shows up
+fn to_here(address: &str) -> Filter {
+    Box::new(
+        dsl::server_addr
+            .eq(address.to_owned())
+            .or(dsl::source_network.is_not_null()),
+    )
+}
+
+// Tells that were sent on this server
+fn from_here(cfg: &ServerCfg) -> Filter {
+    Box::new(
+        dsl::server_addr
+            .eq(cfg.address.clone())
+            .and(dsl::source_network.is_null())
+            .or(dsl::source_network.eq(network(cfg).to_owned())),
+    )
+}
+
+// Splits `nick@network` into the nick, and the address of the network if it's another one
+fn resolve<'a>(
+    cfg: &ServerCfg,
+    recipient: &'a str,
+) -> ::std::result::Result<(&'a str, Option<String>), String> {
+    let i = if let Some(i) = recipient.rfind('@') {
+        i
+    } else {
+        return Ok((recipient, None));
+    };
+    let (nick, net) = (&recipient[..i], &recipient[i + 1..]);
+    if net.to_lowercase() == network(cfg).to_lowercase() {
+        return Ok((nick, None));
+    } else if is_channel(nick) {
+        return Err("Tells to channels of other networks are not supported.".to_owned());
+    }
+    match NETWORKS.read().get(&net.to_lowercase()) {
+        Some(&(ref address, ref database)) if *database == cfg.database => {
+            Ok((nick, Some(address.clone())))
+        }
+        Some(_) => Err(format!("{} does not share my database.", net)),
+        None => Err(format!("I don't know the network {}.", net)),
+    }
+}
+
 pub fn handle_user_join(
     cfg: &ServerCfg,
     srv: &IrcClient,
     log: &Logger,
     msg: &Message,
 ) -> Result<()> {
-    let hm = PENDING_TELLS.lock();
-    let mut pending = hm.get(&cfg.address).unwrap().lock();
-    if *pending != 0 {
-        if let Command::JOIN(ref chan, ..) = msg.command {
-            let target_nick = msg.source_nickname().unwrap();
-
-            let tells = super::with_database(cfg, |db| {
-                Ok(dsl::pending_tells
-                    .filter(dsl::server_addr.eq(&cfg.address))
-                    .filter(dsl::target_nick.eq(&target_nick))
-                    .filter(dsl::channel.eq(&*chan).or(dsl::channel.is_null()))
-                    .load::<models::PendingTell>(db)?)
-            })?;
-
-            *pending -= 1;
-            drop(pending);
-            debug!(log, "Found pending tells: {:?}", tells);
-
-            super::with_database(cfg, |db| {
-                diesel::delete(
-                    dsl::pending_tells
-                        .filter(dsl::server_addr.eq(&cfg.address))
-                        .filter(dsl::target_nick.eq(&target_nick))
-                        .filter(dsl::channel.eq(&*chan).or(dsl::channel.is_null())),
-                ).execute(db)?;
-                Ok(())
-            }).or_else(|err| {
-                srv.send_privmsg(
-                    target_nick,
-                    "You have some pending tells, but I failed. \
-                     Try rejoining, or notifying my owner.",
-                )?;
-                Err(err)
-            })
-                .and_then(|_| send_tells(cfg, srv, log, &tells))
-        } else {
-            unreachable!()
+    if let Command::JOIN(ref chan, ..) = msg.command {
+        if deliver_on_join(cfg, chan) {
+            let target_nick = msg.source_nickname().unwrap().to_owned();
+            deliver(cfg, srv, log, Some(chan), &[target_nick], false)?;
         }
-    } else {
         Ok(())
+    } else {
+        unreachable!()
     }
 }
 
@@ -108,130 +188,727 @@ pub fn handle_names_reply(
     log: &Logger,
     msg: &Message,
 ) -> Result<()> {
-    let hm = PENDING_TELLS.lock();
-    let mut pending = hm.get(&cfg.address).unwrap().lock();
-    if *pending != 0 {
-        if let Command::Response(Response::RPL_NAMREPLY, ref chan, ref users) = msg.command {
-            debug_assert_eq!(cfg.nickname, chan[0]);
-            let chan = &chan[2];
-            let target_nicks = users
-                .as_ref()
-                .unwrap()
-                .split(' ')
-                .filter(|u| u != &cfg.nickname)
-                .map(|u| {
-                    u.replace('%', "")
-                        .replace('~', "")
-                        .replace('@', "")
-                        .replace('+', "")
-                        .replace('&', "")
-                })
-                .collect::<Vec<_>>();
-
-            let tells = super::with_database(cfg, |db| {
-                Ok(dsl::pending_tells
-                    .filter(dsl::server_addr.eq(&cfg.address))
-                    .filter(dsl::target_nick.eq_any(&target_nicks))
-                    .filter(dsl::channel.eq(&*chan).or(dsl::channel.is_null()))
-                    .load::<models::PendingTell>(db)?)
-            })?;
-            *pending -= tells.len();
-            drop(pending);
-            debug!(log, "Found pending tells: {:?}", tells);
-
-            super::with_database(cfg, |db| {
-                diesel::delete(
-                    dsl::pending_tells
-                        .filter(dsl::server_addr.eq(&cfg.address))
-                        .filter(dsl::target_nick.eq_any(&target_nicks))
-                        .filter(dsl::channel.eq(&*chan).or(dsl::channel.is_null())),
-                ).execute(db)?;
-                Ok(())
-            }).or_else(|err| {
-                for target_nick in target_nicks {
-                    srv.send_privmsg(
-                        &target_nick,
-                        "You have some pending tells, but I failed. \
-                         Try rejoining, or notifying my owner.",
-                    )?;
-                }
-                Err(err)
-            })
-                .and_then(|_| send_tells(cfg, srv, log, &tells))
-        } else {
-            unreachable!()
+    if let Command::Response(Response::RPL_NAMREPLY, ref chan, ref users) = msg.command {
+        debug_assert_eq!(cfg.nickname, chan[0]);
+        let chan = &chan[2];
+        if !deliver_on_join(cfg, chan) {
+            return Ok(());
         }
-    } else {
+        let target_nicks = users
+            .as_ref()
+            .unwrap()
+            .split(' ')
+            .filter(|u| u != &cfg.nickname)
+            .map(|u| {
+                u.replace('%', "")
+                    .replace('~', "")
+                    .replace('@', "")
+                    .replace('+', "")
+                    .replace('&', "")
+            })
+            .chain(Some(chan.clone()))
+            .collect::<Vec<_>>();
+        deliver(cfg, srv, log, Some(chan), &target_nicks, false)?;
         Ok(())
+    } else {
+        unreachable!()
     }
 }
 
+/// Delivers pending tells to the sender of a message, in the channel it was sent to, or,
+/// if `chan` is None, in a PM. Tells to the channel itself are delivered too.
+pub fn handle_user_message(
+    cfg: &ServerCfg,
+    srv: &IrcClient,
+    log: &Logger,
+    nick: &str,
+    chan: Option<&str>,
+) -> Result<()> {
+    let mut targets = vec![nick.to_owned()];
+    targets.extend(chan.map(str::to_owned));
+    deliver(cfg, srv, log, chan, &targets, false)?;
+    Ok(())
+}
+
+// Join delivery is optional, by default tells are delivered when the target speaks
+fn deliver_on_join(cfg: &ServerCfg, chan: &str) -> bool {
+    cfg.channels
+        .iter()
+        .find(|c| c.name == chan)
+        .and_then(|c| c.tell_on_join)
+        .unwrap_or(false)
+}
+
+// Delivers the tells of `target_nicks` that were sent in `chan`, or privately. For the `.tells`
+// inbox, all of their tells are delivered privately, even snoozed ones. Returns how many.
+fn deliver(
+    cfg: &ServerCfg,
+    srv: &IrcClient,
+    log: &Logger,
+    chan: Option<&str>,
+    target_nicks: &[String],
+    inbox: bool,
+) -> Result<usize> {
+    let targets = target_nicks
+        .iter()
+        .map(|n| super::irc_lowercase(n))
+        .collect::<HashSet<_>>();
+    let any_pending = PENDING
+        .read()
+        .get(&cfg.address)
+        .map_or(false, |pending| !pending.is_disjoint(&targets));
+    if !any_pending {
+        return Ok(0);
+    }
+
+    let now = Utc::now().naive_utc();
+    let tells = super::with_database(cfg, |db| {
+        let query = dsl::pending_tells
+            .filter(to_here(&cfg.address))
+            .into_boxed();
+        let query = if inbox {
+            query
+        } else {
+            let query = query.filter(dsl::snoozed_until.is_null().or(dsl::snoozed_until.lt(now)));
+            if let Some(chan) = chan {
+                query.filter(dsl::channel.eq(chan).or(dsl::channel.is_null()))
+            } else {
+                query.filter(dsl::channel.is_null())
+            }
+        };
+        Ok(query.load::<models::PendingTell>(db)?)
+    })?;
+    // Nicks are compared case insensitively, which SQLite can't do. Tells that are already
+    // queued, e.g. because the target spoke twice, are not queued again.
+    let tells = {
+        let mut queued = QUEUED.lock();
+        tells
+            .into_iter()
+            .filter(|t| targets.contains(&super::irc_lowercase(&t.target_nick)))
+            .filter(|t| queued.insert((cfg.database.clone(), t.id)))
+            .collect::<Vec<_>>()
+    };
+    if tells.is_empty() {
+        return Ok(0);
+    }
+    debug!(log, "Queued pending tells: {:?}", tells);
+    let num = tells.len();
+    send_tells(cfg, srv, log, chan, tells)?;
+    Ok(num)
+}
+
+// Queues the tells, they are only deleted once they were sent. If they are dropped from the
+// queue instead, they stay pending.
 fn send_tells(
     cfg: &ServerCfg,
     srv: &IrcClient,
     log: &Logger,
-    tells: &[models::PendingTell],
+    chan: Option<&str>,
+    tells: Vec<models::PendingTell>,
 ) -> Result<()> {
+    let private = chan.map(|chan| {
+        cfg.channels
+            .iter()
+            .find(|c| c.name == chan)
+            .and_then(|c| c.tell_private)
+            .unwrap_or(false)
+    }).unwrap_or(true);
     for t in tells {
+        let source = if let Some(ref net) = t.source_network {
+            format!("{} on {}", t.source_nick, net)
+        } else {
+            t.source_nick.clone()
+        };
+        // Shown in the recipient's timezone, if they set one
+        let sent = Utc.from_utc_datetime(&t.date);
+        let local = prefs::get(cfg, &t.target_nick, "timezone")
+            .and_then(|tz| tz.parse::<Tz>().ok())
+            .map(|tz| format!(" ({})", sent.with_timezone(&tz).format("%Y-%m-%d %H:%M %Z")))
+            .unwrap_or_default();
         let msg = format!(
-            "{}: {} wanted to tell you on {} UTC: {}",
-            &t.target_nick, t.source_nick, t.date, t.message
+            "{}: {} wanted to tell you {}{}: {}",
+            &t.target_nick,
+            source,
+            relative(Utc::now().signed_duration_since(sent)),
+            local,
+            t.message
         );
-        if t.channel.is_some() {
-            super::send_segmented_message(cfg, srv, log, t.channel.as_ref().unwrap(), &msg)?;
-        } else {
-            super::send_segmented_message(cfg, srv, log, &t.target_nick, &msg)?;
+        // Tells to a channel are always delivered there, tells sent privately (including
+        // receipts and expiry notices, which quote them) are never shown in a channel
+        let target = match (chan, &t.channel) {
+            (Some(chan), &Some(_)) if !private && !is_channel(&t.target_nick) => chan.to_owned(),
+            _ => t.target_nick.clone(),
+        };
+
+        let id = t.id;
+        let on_sent = {
+            let (cfg, srv, log) = (cfg.clone(), srv.clone(), log.clone());
+            Box::new(move |sent: bool| {
+                if sent {
+                    if let Err(e) = delivered(&cfg, &srv, &log, &t) {
+                        crit!(log, "Failed to finish delivering tell #{}: {:?}", t.id, e);
+                    }
+                }
+                QUEUED.lock().remove(&(cfg.database.clone(), t.id));
+            })
+        };
+        if let Err(e) = super::send_segmented_message_then(
+            cfg,
+            log,
+            &target,
+            "tell",
+            &msg,
+            Priority::Bulk,
+            on_sent,
+        ) {
+            QUEUED.lock().remove(&(cfg.database.clone(), id));
+            return Err(e);
         }
     }
     Ok(())
 }
 
-pub fn add(cfg: &ServerCfg, log: &Logger, msg: &Message, private: bool) -> Result<String> {
-    if let Command::PRIVMSG(ref target, ref content) = msg.command {
-        let source_nick = msg.source_nickname().unwrap();
+// Deletes a tell that was sent, remembers its sender for `.reply`, and sends a receipt if
+// they want one
+fn delivered(
+    cfg: &ServerCfg,
+    srv: &IrcClient,
+    log: &Logger,
+    t: &models::PendingTell,
+) -> Result<()> {
+    let deleted = super::with_database(cfg, |db| {
+        Ok(diesel::delete(dsl::pending_tells.filter(dsl::id.eq(t.id))).execute(db)?)
+    })?;
+    // It was cancelled while queued
+    if deleted == 0 {
+        return Ok(());
+    }
+    debug!(log, "Delivered pending tell: {:?}", t);
+    resync(cfg)?;
 
-        let mut split = content[6..].splitn(2, ' ');
-        let target_nick = split.next().unwrap();
-        let target_msg = if let Some(s) = split.next() {
-            s
+    // Tells by the bot itself, e.g. receipts, can't be replied to
+    if t.source_nick == cfg.nickname {
+        return Ok(());
+    }
+    LAST_DELIVERED.lock().insert(
+        (cfg.address.clone(), super::irc_lowercase(&t.target_nick)),
+        if let Some(ref net) = t.source_network {
+            format!("{}@{}", t.source_nick, net)
         } else {
+            t.source_nick.clone()
+        },
+    );
+    // Preferences are per network, so there are no receipts for tells from other networks
+    if t.source_network.is_none() && prefs::enabled(cfg, &t.source_nick, "receipts") {
+        let receipt = format!(
+            "Your tell #{} to {} was delivered: {}",
+            t.id, t.target_nick, t.message
+        );
+        if is_online(srv, &t.source_nick) {
+            super::send_segmented_message(
+                cfg,
+                log,
+                &t.source_nick,
+                None,
+                "tell",
+                &receipt,
+                Priority::Bulk,
+            )?;
+        } else {
+            tell_from_bot(cfg, &t.source_nick, &receipt)?;
+        }
+    }
+    Ok(())
+}
+
+// Whether `nick` is in any channel shared with the bot
+fn is_online(srv: &IrcClient, nick: &str) -> bool {
+    let nick = super::irc_lowercase(nick);
+    srv.list_channels().unwrap_or_default().iter().any(|chan| {
+        srv.list_users(chan)
+            .unwrap_or_default()
+            .iter()
+            .any(|u| super::irc_lowercase(u.get_nickname()) == nick)
+    })
+}
+
+// Queues a tell by the bot, e.g. for notifications to offline users
+fn tell_from_bot(cfg: &ServerCfg, target_nick: &str, message: &str) -> Result<()> {
+    let new = models::NewPendingTell {
+        date: Utc::now().naive_utc(),
+        server_addr: &cfg.address,
+        channel: None,
+        source_nick: &cfg.nickname,
+        target_nick: target_nick,
+        message: message,
+        expires_at: None,
+        source_network: None,
+    };
+    super::with_database(cfg, |db| {
+        diesel::insert_into(schema::pending_tells::table)
+            .values(&new)
+            .execute(db)?;
+        Ok(())
+    })?;
+    resync(cfg)
+}
+
+/// `.reply <message>` answers the last tell delivered to the user with a tell to its sender
+pub fn reply(
+    cfg: &ServerCfg,
+    log: &Logger,
+    msg: &Message,
+    args: &str,
+    prefix: &str,
+    private: bool,
+) -> Result<String> {
+    let nick = msg.source_nickname().unwrap();
+    let source = LAST_DELIVERED
+        .lock()
+        .get(&(cfg.address.clone(), super::irc_lowercase(nick)))
+        .cloned();
+    match source {
+        Some(_) if args.is_empty() => Ok(format!(
+            "Invalid `{0}reply` syntax, try: `{0}reply <message>`",
+            prefix
+        )),
+        Some(source) => add(cfg, log, msg, &format!("{} {}", source, args), prefix, private),
+        None => Ok(format!("{}: No tell was delivered to you lately.", nick)),
+    }
+}
+
+/// `.tell cancel|edit|snooze …`, or else adds a tell
+pub fn handle(
+    cfg: &ServerCfg,
+    log: &Logger,
+    msg: &Message,
+    args: &str,
+    prefix: &str,
+    private: bool,
+) -> Result<String> {
+    let nick = msg.source_nickname().unwrap();
+    let mut split = args.splitn(2, ' ');
+    let sub = split.next().unwrap();
+    let rest = split.next().unwrap_or("").trim();
+    match sub {
+        "cancel" => cancel(cfg, log, nick, rest, prefix),
+        "edit" => edit(cfg, log, nick, rest, prefix),
+        "snooze" => snooze(cfg, log, nick, rest, prefix),
+        _ => add(cfg, log, msg, args, prefix, private),
+    }
+}
+
+fn add(
+    cfg: &ServerCfg,
+    log: &Logger,
+    msg: &Message,
+    args: &str,
+    prefix: &str,
+    private: bool,
+) -> Result<String> {
+    if let Command::PRIVMSG(ref target, _) = msg.command {
+        let source_nick = msg.source_nickname().unwrap();
+        let invalid = || -> Result<String> {
             trace!(log, "invalid tell: {:?}", msg);
-            return Ok("Invalid `.tell` syntax, try: `.tell <nick> <message>`".into());
+            Ok(format!(
+                "Invalid `{0}tell` syntax, try: `{0}tell <nick>[,<nick|#channel>…] \
+                 [--expires <n><m|h|d>] <message>`",
+                prefix
+            ))
         };
 
-        let date = &Utc::now().to_rfc2822()[..25];
-        let pending_tell = models::NewPendingTell {
-            date: date,
-            server_addr: &cfg.address,
-            channel: {
-                if private {
-                    None
-                } else {
-                    Some(target)
+        // `<recipients> [--expires <duration>] <message>`, the option may also come first
+        let (mut recipients, mut expires, mut rest) = (None, None, args.trim());
+        loop {
+            let mut split = rest.splitn(2, ' ');
+            let word = split.next().unwrap();
+            if word == "--expires" && expires.is_none() {
+                let mut split = split.next().unwrap_or("").trim().splitn(2, ' ');
+                expires = split.next().and_then(parse_duration);
+                if expires.is_none() {
+                    return invalid();
                 }
-            },
-            source_nick: source_nick,
-            target_nick: target_nick,
-            message: target_msg.trim(),
+                rest = split.next().unwrap_or("").trim();
+            } else if recipients.is_none() && !word.is_empty() {
+                recipients = Some(word);
+                rest = split.next().unwrap_or("").trim();
+            } else {
+                break;
+            }
+        }
+        let target_msg = rest;
+        let mut recipients = match recipients {
+            Some(recipients) if !target_msg.is_empty() => recipients
+                .split(',')
+                .filter(|r| !r.is_empty())
+                .collect::<Vec<_>>(),
+            _ => return invalid(),
         };
+        recipients.sort_by_key(|r| super::irc_lowercase(r));
+        recipients.dedup_by_key(|r| super::irc_lowercase(r));
+        let mut resolved = Vec::with_capacity(recipients.len());
+        for recipient in &recipients {
+            match resolve(cfg, recipient) {
+                Ok((nick, None)) if is_channel(nick)
+                    && !cfg.channels
+                        .iter()
+                        .any(|c| super::irc_lowercase(&c.name) == super::irc_lowercase(nick)) =>
+                {
+                    return Ok(format!("{}: I'm not in {}.", source_nick, nick));
+                }
+                Ok(r) => resolved.push(r),
+                Err(e) => return Ok(format!("{}: {}", source_nick, e)),
+            }
+        }
 
-        super::with_database(cfg, |db| {
-            diesel::insert_into(schema::pending_tells::table)
-                .values(&pending_tell)
-                .execute(db)?;
-            Ok(())
-        }).and_then(|_| {
-            let mut hm = PENDING_TELLS.lock();
-            *hm.get_mut(&cfg.address).unwrap().lock() += 1;
+        // Limit the number of pending tells per user, so the database can't be flooded
+        let max_pending = cfg.tell_max_pending.unwrap_or(DEFAULT_MAX_PENDING);
+        // Nicks are compared case insensitively, which SQLite can't do
+        let lower = super::irc_lowercase(source_nick);
+        let pending = super::with_database(cfg, |db| {
+            Ok(dsl::pending_tells
+                .select(dsl::source_nick)
+                .filter(from_here(cfg))
+                .load::<String>(db)?)
+        })?
+            .iter()
+            .filter(|n| super::irc_lowercase(n) == lower)
+            .count();
+        if pending + recipients.len() > max_pending {
+            return Ok(format!(
+                "{}: You may only have {} pending tells, see `{}tells sent`.",
+                source_nick, max_pending, prefix
+            ));
+        }
 
-            Ok(format!(
-                "{}: I will tell {}: {}",
-                source_nick,
-                target_nick,
-                target_msg.trim()
+        let date = Utc::now().naive_utc();
+        let expires_at = expires.map(|e| (Utc::now() + e).naive_utc());
+        let ids = super::with_database(cfg, |db| {
+            db.transaction(|| {
+                let mut ids = Vec::with_capacity(resolved.len());
+                for &(nick, ref address) in &resolved {
+                    let pending_tell = models::NewPendingTell {
+                        date: date,
+                        server_addr: address.as_ref().unwrap_or(&cfg.address),
+                        // Tells to a channel are delivered there, tells to other networks in
+                        // any channel
+                        channel: if is_channel(nick) {
+                            Some(nick)
+                        } else if private || address.is_some() {
+                            None
+                        } else {
+                            Some(target)
+                        },
+                        source_nick: source_nick,
+                        target_nick: nick,
+                        message: target_msg,
+                        expires_at: expires_at,
+                        source_network: address.as_ref().map(|_| network(cfg)),
+                    };
+                    diesel::insert_into(schema::pending_tells::table)
+                        .values(&pending_tell)
+                        .execute(db)?;
+                    ids.push(diesel::select(last_insert_rowid).get_result::<i32>(db)?);
+                }
+                Ok(ids)
+            })
+        })?;
+        resync(cfg)?;
+
+        Ok(format!(
+            "{}: I will tell {}{}: {}",
+            source_nick,
+            recipients
+                .iter()
+                .zip(&ids)
+                .map(|(r, id)| format!("{} (#{})", r, id))
+                .collect::<Vec<_>>()
+                .join(", "),
+            expires_at
+                .map(|e| format!(", unless not delivered by {} UTC", e.format("%Y-%m-%d %H:%M")))
+                .unwrap_or_default(),
+            target_msg
+        ))
+    } else {
+        unreachable!()
+    }
+}
+
+// E.g. `3 days ago`
+fn relative(elapsed: Duration) -> String {
+    let (n, unit) = if elapsed.num_days() != 0 {
+        (elapsed.num_days(), "day")
+    } else if elapsed.num_hours() != 0 {
+        (elapsed.num_hours(), "hour")
+    } else if elapsed.num_minutes() != 0 {
+        (elapsed.num_minutes(), "minute")
+    } else {
+        return "just now".to_owned();
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
+
+fn is_channel(target: &str) -> bool {
+    target.starts_with('#') || target.starts_with('&')
+}
+
+/// Drops expired tells every minute and tells their senders, never returns
+pub fn run(cfg: &ServerCfg, log: &Logger) -> Result<()> {
+    loop {
+        thread::sleep(StdDuration::from_secs(60));
+        if let Err(e) = expire(cfg, log) {
+            crit!(log, "Failed to expire tells: {:?}", e);
+        }
+    }
+}
+
+fn expire(cfg: &ServerCfg, log: &Logger) -> Result<()> {
+    let now = Utc::now().naive_utc();
+    let date = Utc::now().naive_utc();
+    // Queued tells are about to be delivered
+    let queued = QUEUED.lock().clone();
+    let expired = super::with_database(cfg, |db| {
+        db.transaction(|| {
+            let expired = dsl::pending_tells
+                .filter(from_here(cfg))
+                .filter(dsl::expires_at.lt(now))
+                .load::<models::PendingTell>(db)?
+                .into_iter()
+                .filter(|t| !queued.contains(&(cfg.database.clone(), t.id)))
+                .collect::<Vec<_>>();
+            for t in &expired {
+                diesel::delete(dsl::pending_tells.filter(dsl::id.eq(t.id))).execute(db)?;
+                // The sender is told like any other user, they may not be online now
+                let message = format!(
+                    "Your tell #{} to {} expired undelivered: {}",
+                    t.id, t.target_nick, t.message
+                );
+                let notice = models::NewPendingTell {
+                    date: date,
+                    server_addr: &cfg.address,
+                    channel: None,
+                    source_nick: &cfg.nickname,
+                    target_nick: &t.source_nick,
+                    message: &message,
+                    expires_at: None,
+                    source_network: None,
+                };
+                diesel::insert_into(schema::pending_tells::table)
+                    .values(&notice)
+                    .execute(db)?;
+            }
+            Ok(expired)
+        })
+    })?;
+    if !expired.is_empty() {
+        debug!(log, "Expired tells: {:?}", expired);
+        resync(cfg)?;
+    }
+    Ok(())
+}
+
+// Ids are shown as `#12`, but may be given without the `#`
+fn parse_id(s: &str) -> Option<i32> {
+    s.trim_start_matches('#').parse().ok()
+}
+
+// Parses e.g. `30m`, `12h` or `7d`
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (i, unit) = s.char_indices().last()?;
+    let n = s[..i].parse::<i64>().ok().filter(|n| *n > 0)?;
+    match unit {
+        'm' => Some(Duration::minutes(n)),
+        'h' => Some(Duration::hours(n)),
+        'd' => Some(Duration::days(n)),
+        _ => None,
+    }
+}
+
+// Loads the tell with `id`, if `nick` sent it, or is an owner
+fn load_own(cfg: &ServerCfg, nick: &str, id: i32) -> Result<Option<models::PendingTell>> {
+    let tell = super::with_database(cfg, |db| {
+        Ok(dsl::pending_tells
+            .filter(from_here(cfg))
+            .filter(dsl::id.eq(id))
+            .first::<models::PendingTell>(db)
+            .optional()?)
+    })?;
+    Ok(tell.filter(|t| {
+        super::irc_lowercase(&t.source_nick) == super::irc_lowercase(nick)
+            || cfg.owners.iter().any(|o| o == nick)
+    }))
+}
+
+fn cancel(cfg: &ServerCfg, log: &Logger, nick: &str, args: &str, prefix: &str) -> Result<String> {
+    let id = if let Some(id) = parse_id(args) {
+        id
+    } else {
+        return Ok(format!(
+            "Invalid `{0}tell cancel` syntax, try: `{0}tell cancel <id>`",
+            prefix
+        ));
+    };
+    if load_own(cfg, nick, id)?.is_none() {
+        return Ok(format!("{}: You have no pending tell #{}.", nick, id));
+    }
+
+    super::with_database(cfg, |db| {
+        diesel::delete(dsl::pending_tells.filter(dsl::id.eq(id))).execute(db)?;
+        Ok(())
+    })?;
+    resync(cfg)?;
+    debug!(log, "Cancelled tell #{}", id);
+
+    Ok(format!("{}: Cancelled tell #{}.", nick, id))
+}
+
+fn edit(cfg: &ServerCfg, log: &Logger, nick: &str, args: &str, prefix: &str) -> Result<String> {
+    let mut split = args.splitn(2, ' ');
+    let (id, message) = match (split.next().and_then(parse_id), split.next()) {
+        (Some(id), Some(message)) if !message.trim().is_empty() => (id, message.trim()),
+        _ => {
+            return Ok(format!(
+                "Invalid `{0}tell edit` syntax, try: `{0}tell edit <id> <message>`",
+                prefix
             ))
+        }
+    };
+    if load_own(cfg, nick, id)?.is_none() {
+        return Ok(format!("{}: You have no pending tell #{}.", nick, id));
+    }
+
+    super::with_database(cfg, |db| {
+        diesel::update(dsl::pending_tells.filter(dsl::id.eq(id)))
+            .set(dsl::message.eq(message))
+            .execute(db)?;
+        Ok(())
+    })?;
+    debug!(log, "Edited tell #{}", id);
+
+    Ok(format!("{}: Tell #{} is now: {}", nick, id, message))
+}
+
+// Postpones the delivery of all tells to `nick`
+fn snooze(cfg: &ServerCfg, log: &Logger, nick: &str, args: &str, prefix: &str) -> Result<String> {
+    let duration = if args.is_empty() {
+        Duration::hours(1)
+    } else if let Some(duration) = parse_duration(args) {
+        duration
+    } else {
+        return Ok(format!(
+            "Invalid `{0}tell snooze` syntax, try: `{0}tell snooze [<n><m|h|d>]`",
+            prefix
+        ));
+    };
+
+    let until = (Utc::now() + duration).naive_utc();
+    let lower = super::irc_lowercase(nick);
+    let snoozed = super::with_database(cfg, |db| {
+        db.transaction(|| {
+            // Nicks are compared case insensitively, which SQLite can't do
+            let ids = dsl::pending_tells
+                .filter(to_here(&cfg.address))
+                .load::<models::PendingTell>(db)?
+                .into_iter()
+                .filter(|t| super::irc_lowercase(&t.target_nick) == lower)
+                .map(|t| t.id)
+                .collect::<Vec<_>>();
+            Ok(
+                diesel::update(dsl::pending_tells.filter(dsl::id.eq_any(&ids)))
+                    .set(dsl::snoozed_until.eq(until))
+                    .execute(db)?,
+            )
         })
+    })?;
+    debug!(log, "Snoozed {} tells for {} until {}", snoozed, nick, until);
+
+    if snoozed == 0 {
+        Ok(format!("{}: You have no pending tells.", nick))
     } else {
-        unreachable!()
+        Ok(format!(
+            "{}: Snoozed {} tell(s) until {} UTC.",
+            nick,
+            snoozed,
+            until.format("%Y-%m-%d %H:%M")
+        ))
+    }
+}
+
+/// `.tells` delivers the user's pending tells by PM, `.tells sent` lists the tells the user
+/// sent, and `.tells all` lists all pending tells of the server for owners
+pub fn handle_tells(
+    cfg: &ServerCfg,
+    srv: &IrcClient,
+    log: &Logger,
+    nick: &str,
+    args: &str,
+    prefix: &str,
+) -> Result<String> {
+    let format_tells = |tells: &[models::PendingTell]| {
+        tells
+            .iter()
+            .map(|t| {
+                format!(
+                    "#{} {} → {}{}: {}",
+                    t.id,
+                    t.source_nick,
+                    t.target_nick,
+                    t.channel
+                        .as_ref()
+                        .map(|c| format!(" in {}", c))
+                        .unwrap_or_default(),
+                    t.message
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    };
+
+    match args {
+        "" => {
+            // Snoozed tells are included, the user asked for them
+            match deliver(cfg, srv, log, None, &[nick.to_owned()], true)? {
+                0 => Ok(format!("{}: You have no pending tells.", nick)),
+                n => Ok(format!("{}: Sent you {} tell(s) by PM.", nick, n)),
+            }
+        }
+        "sent" => {
+            let lower = super::irc_lowercase(nick);
+            let tells = super::with_database(cfg, |db| {
+                Ok(dsl::pending_tells
+                    .filter(from_here(cfg))
+                    .order(dsl::id)
+                    .load::<models::PendingTell>(db)?)
+            })?
+                .into_iter()
+                .filter(|t| super::irc_lowercase(&t.source_nick) == lower)
+                .collect::<Vec<_>>();
+            if tells.is_empty() {
+                Ok(format!("{}: None of your tells are pending.", nick))
+            } else {
+                Ok(format!("{}: {}", nick, format_tells(&tells)))
+            }
+        }
+        "all" if cfg.owners.iter().any(|o| o == nick) => {
+            let tells = super::with_database(cfg, |db| {
+                Ok(dsl::pending_tells
+                    .filter(to_here(&cfg.address).or(from_here(cfg)))
+                    .order(dsl::id)
+                    .load::<models::PendingTell>(db)?)
+            })?;
+            if tells.is_empty() {
+                Ok("No tells are pending.".to_owned())
+            } else {
+                Ok(format!(
+                    "{} pending tell(s): {}",
+                    tells.len(),
+                    format_tells(&tells)
+                ))
+            }
+        }
+        _ => Ok(format!(
+            "Invalid `{0}tells` syntax, try: `{0}help tell`",
+            prefix
+        )),
     }
 }
@@ -36,6 +36,7 @@ use std::io::{Cursor, Read};
 
 use config::ServerCfg;
 use errors::*;
+use super::format;
 use super::module_enabled_channel;
 
 pub fn handle(cfg: &ServerCfg, url: Url, target: &str, regex_match: bool) -> Result<String> {
@@ -187,8 +188,8 @@ pub fn handle(cfg: &ServerCfg, url: Url, target: &str, regex_match: bool) -> Res
         {
             let url = item.pointer("/link").unwrap().as_str().unwrap();
             formatted.push_str(&format!(
-                "\x02{}\x02: {} [{}]{}",
-                n + 1,
+                "{}: {} [{}]{}",
+                format::bold(n + 1),
                 shortener.try_generate(url, Some(&[Provider::IsGd, Provider::VGd, Provider::HmmRs]))?,
                 item.pointer("/snippet")
                     .unwrap()
@@ -324,6 +325,7 @@ mod jisho {
     use reqwest;
 
     use errors::*;
+    use modules::format;
 
     const API_BASE: &str = "http://jisho.org/api/v1/search/words?keyword=";
 
@@ -368,9 +370,9 @@ mod jisho {
 
         for (n, dp) in resp.iter().take(3).enumerate() {
             if n == 0 {
-                ret.push_str("\x021\x02: ");
+                ret.push_str(&format!("{}: ", format::bold(1)));
             } else {
-                ret.push_str(&format!("; \x02{}\x02: ", n + 1));
+                ret.push_str(&format!("; {}: ", format::bold(n + 1)));
             }
             let mut senses = String::new();
             for (n, s) in dp.senses.iter().take(3).enumerate() {
@@ -0,0 +1,185 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use chrono::prelude::*;
+
+use std::f64::consts::PI;
+
+// Julian day of the unix epoch, and of J2000
+const UNIX_EPOCH_JD: f64 = 2_440_587.5;
+const J2000: f64 = 2_451_545.0;
+// Mean length of a lunar cycle in days
+const SYNODIC_MONTH: f64 = 29.530_588_853;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Daylight {
+    /// Sunrise and sunset
+    Between(DateTime<Utc>, DateTime<Utc>),
+    PolarDay,
+    PolarNight,
+}
+
+/// The daylight on `date` at a location, using the sunrise equation
+/// (https://en.wikipedia.org/wiki/Sunrise_equation), which is accurate to about a minute
+pub fn daylight(latitude: f64, longitude: f64, date: NaiveDate) -> Daylight {
+    let (sin, cos) = (|d: f64| d.to_radians().sin(), |d: f64| d.to_radians().cos());
+
+    // Days since J2000 at noon of `date`
+    let n = f64::from(date.num_days_from_ce() - 730_120) + 0.0008;
+    let mean_noon = n - longitude / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * mean_noon) % 360.0;
+    let center = 1.9148 * sin(anomaly) + 0.02 * sin(2.0 * anomaly) + 0.0003 * sin(3.0 * anomaly);
+    let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372) % 360.0;
+    let transit =
+        J2000 + mean_noon + 0.0053 * sin(anomaly) - 0.0069 * sin(2.0 * ecliptic_longitude);
+    let declination = (sin(ecliptic_longitude) * sin(23.44)).asin().to_degrees();
+    // -0.833° accounts for refraction and the sun's size
+    let cos_hour_angle = (sin(-0.833) - sin(latitude) * sin(declination))
+        / (cos(latitude) * cos(declination));
+    if cos_hour_angle > 1.0 {
+        return Daylight::PolarNight;
+    } else if cos_hour_angle < -1.0 {
+        return Daylight::PolarDay;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    Daylight::Between(
+        from_julian(transit - hour_angle / 360.0),
+        from_julian(transit + hour_angle / 360.0),
+    )
+}
+
+fn from_julian(jd: f64) -> DateTime<Utc> {
+    Utc.timestamp(((jd - UNIX_EPOCH_JD) * 86_400.0).round() as i64, 0)
+}
+
+/// Where in the lunar cycle the moon is at `time`, from 0 (new moon) over 0.5 (full moon) to 1
+pub fn moon_phase(time: DateTime<Utc>) -> f64 {
+    let jd = time.timestamp() as f64 / 86_400.0 + UNIX_EPOCH_JD;
+    // A new moon
+    let age = (jd - 2_451_550.1) / SYNODIC_MONTH;
+    age - age.floor()
+}
+
+/// The illuminated fraction of the moon at `phase`
+pub fn illumination(phase: f64) -> f64 {
+    (1.0 - (2.0 * PI * phase).cos()) / 2.0
+}
+
+pub fn phase_name(phase: f64) -> &'static str {
+    // Named phases last about a day around their exact time
+    match phase {
+        p if p < 0.0339 => "new moon",
+        p if p < 0.216 => "waxing crescent",
+        p if p < 0.284 => "first quarter",
+        p if p < 0.466 => "waxing gibbous",
+        p if p < 0.534 => "full moon",
+        p if p < 0.716 => "waning gibbous",
+        p if p < 0.784 => "last quarter",
+        p if p < 0.966 => "waning crescent",
+        _ => "new moon",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Published times are accurate to the minute, the equation to about one more
+    fn assert_close(actual: DateTime<Utc>, expected: &str) {
+        let expected = Utc.datetime_from_str(expected, "%Y-%m-%d %H:%M").unwrap();
+        let diff = actual.signed_duration_since(expected).num_seconds().abs();
+        assert!(diff <= 150, "{} is not close to {}", actual, expected);
+    }
+
+    #[test]
+    fn daylight_berlin() {
+        match daylight(52.52, 13.405, NaiveDate::from_ymd(2026, 6, 21)) {
+            Daylight::Between(rise, set) => {
+                assert_close(rise, "2026-06-21 02:43");
+                assert_close(set, "2026-06-21 19:33");
+            }
+            d => panic!("{:?}", d),
+        }
+        match daylight(52.52, 13.405, NaiveDate::from_ymd(2026, 12, 21)) {
+            Daylight::Between(rise, set) => {
+                assert_close(rise, "2026-12-21 07:15");
+                assert_close(set, "2026-12-21 14:54");
+            }
+            d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn daylight_equinox() {
+        // About 12 hours everywhere, a bit more due to refraction
+        for &(lat, lng) in &[(0.0, 0.0), (35.68, 139.69), (-33.87, 151.21)] {
+            match daylight(lat, lng, NaiveDate::from_ymd(2026, 3, 20)) {
+                Daylight::Between(rise, set) => {
+                    let length = set.signed_duration_since(rise).num_minutes();
+                    assert!(720 <= length && length <= 735, "{}", length);
+                }
+                d => panic!("{:?}", d),
+            }
+        }
+    }
+
+    #[test]
+    fn daylight_polar() {
+        match daylight(78.22, 15.65, NaiveDate::from_ymd(2026, 6, 21)) {
+            Daylight::PolarDay => {}
+            d => panic!("{:?}", d),
+        }
+        match daylight(78.22, 15.65, NaiveDate::from_ymd(2026, 12, 21)) {
+            Daylight::PolarNight => {}
+            d => panic!("{:?}", d),
+        }
+        match daylight(-78.22, 15.65, NaiveDate::from_ymd(2026, 6, 21)) {
+            Daylight::PolarNight => {}
+            d => panic!("{:?}", d),
+        }
+    }
+
+    #[test]
+    fn moon() {
+        let at = |s: &str| moon_phase(Utc.datetime_from_str(s, "%Y-%m-%d %H:%M").unwrap());
+        // Full moons
+        for s in &["2026-10-26 04:12", "2026-11-24 14:53"] {
+            let phase = at(s);
+            assert!((phase - 0.5).abs() < 0.03, "{}: {}", s, phase);
+            assert_eq!(phase_name(phase), "full moon");
+            assert!(illumination(phase) > 0.99);
+        }
+        // New moons, the phase may be just below 1
+        for s in &["2000-01-06 18:14", "2026-10-10 15:50"] {
+            let phase = at(s);
+            assert!(phase < 0.03 || phase > 0.97, "{}: {}", s, phase);
+            assert_eq!(phase_name(phase), "new moon");
+            assert!(illumination(phase) < 0.01);
+        }
+    }
+
+    #[test]
+    fn phases() {
+        assert_eq!(phase_name(0.0), "new moon");
+        assert_eq!(phase_name(0.1), "waxing crescent");
+        assert_eq!(phase_name(0.25), "first quarter");
+        assert_eq!(phase_name(0.75), "last quarter");
+        assert_eq!(phase_name(0.99), "new moon");
+        assert!((illumination(0.25) - 0.5).abs() < 1e-9);
+        assert!((illumination(0.5) - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use reqwest::Client;
+use serde_json::Value;
+use slog::Logger;
+
+use errors::*;
+use super::{number, Alert, DataPoint, Forecast, Span, WeatherProvider};
+
+pub const API_BASE: &str = "https://api.darksky.net";
+
+pub struct DarkSky {
+    pub base: String,
+    pub secret: String,
+}
+
+impl WeatherProvider for DarkSky {
+    fn limits(&self) -> (usize, usize) {
+        (168, 7)
+    }
+
+    fn forecast(
+        &self,
+        client: &Client,
+        log: &Logger,
+        latitude: f64,
+        longitude: f64,
+        span: Span,
+    ) -> Result<Forecast> {
+        let exclude = if span == Span::Current {
+            "minutely,hourly,daily"
+        } else {
+            "minutely,currently"
+        };
+        // `ca` units are SI, but with km/h
+        let mut req = client
+            .get(&format!(
+                "{}/forecast/{}/{},{}",
+                self.base, self.secret, latitude, longitude
+            ))
+            .query(&[("units", "ca"), ("exclude", exclude)]);
+        // Only 48h are returned by default
+        if let Span::Hours(n) = span {
+            if n > 49 {
+                req = req.query(&[("extend", "hourly")]);
+            }
+        }
+        let mut res = req.send()?.error_for_status()?;
+
+        if let Some(api_calls) = res.headers()
+            .get("X-Forecast-API-Calls")
+            .and_then(|c| c.to_str().ok())
+            .and_then(|c| c.parse::<usize>().ok())
+        {
+            info!(
+                log,
+                "{} remaining weather API calls (assuming free plan) today",
+                1000usize.saturating_sub(api_calls)
+            );
+        }
+
+        let json: Value = res.json()?;
+        let block = |name: &str, hours: f64| {
+            json.pointer(&format!("/{}/data", name))
+                .and_then(Value::as_array)
+                .map(|data| data.iter().map(|d| data_point(d, hours)).collect())
+                .unwrap_or_default()
+        };
+        Ok(Forecast {
+            timezone: json.get("timezone")
+                .and_then(Value::as_str)
+                .and_then(|tz| tz.parse().ok()),
+            currently: json.get("currently").map(|d| data_point(d, 1.0)),
+            hourly: block("hourly", 1.0),
+            daily: block("daily", 24.0),
+            alerts: json.get("alerts")
+                .and_then(Value::as_array)
+                .map(|alerts| alerts.iter().filter_map(alert).collect())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+// The intensity of precipitation is per hour, which is multiplied by the data point's `hours`
+fn data_point(json: &Value, hours: f64) -> DataPoint {
+    let text = |field: &str| json.get(field).and_then(Value::as_str).map(str::to_owned);
+    DataPoint {
+        summary: text("summary"),
+        temperature: number(json, "temperature"),
+        apparent_temperature: number(json, "apparentTemperature"),
+        temperature_min: number(json, "temperatureMin"),
+        temperature_max: number(json, "temperatureMax"),
+        apparent_temperature_min: number(json, "apparentTemperatureMin"),
+        apparent_temperature_max: number(json, "apparentTemperatureMax"),
+        cloud_cover: number(json, "cloudCover"),
+        humidity: number(json, "humidity"),
+        precip_probability: number(json, "precipProbability"),
+        precip_type: text("precipType"),
+        precip_amount: number(json, "precipIntensity").map(|i| i * hours),
+        wind_speed: number(json, "windSpeed"),
+        wind_bearing: number(json, "windBearing"),
+        pressure: number(json, "pressure"),
+        uv_index: number(json, "uvIndex"),
+        visibility: number(json, "visibility"),
+        dew_point: number(json, "dewPoint"),
+    }
+}
+
+fn alert(json: &Value) -> Option<Alert> {
+    Some(Alert {
+        title: json.get("title")?.as_str()?.to_owned(),
+        regions: json.get("regions")
+            .and_then(Value::as_array)
+            .map(|regions| {
+                regions
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        description: json.get("description")?.as_str()?.to_owned(),
+        expires: json.get("expires")?.as_i64()?,
+    })
+}
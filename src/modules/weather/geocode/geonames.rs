@@ -0,0 +1,170 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use diesel;
+use diesel::prelude::*;
+use reqwest::Client;
+use slog::Logger;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use config::ServerCfg;
+use errors::*;
+use models;
+use modules::with_database;
+use schema;
+use schema::geoname_names::dsl as n_dsl;
+use schema::geonames::dsl as g_dsl;
+use super::{Geocoder, Place};
+
+pub struct GeoNames<'a> {
+    pub cfg: &'a ServerCfg,
+}
+
+impl<'a> Geocoder for GeoNames<'a> {
+    fn geocode(
+        &self,
+        _: &Client,
+        log: &Logger,
+        location: &str,
+    ) -> Result<::std::result::Result<Vec<Place>, String>> {
+        let location = location.trim().to_lowercase();
+        // `Paris, US` is the Paris in the US, other suffixes may be part of the name
+        let mut places = Vec::new();
+        if let Some(i) = location.rfind(',') {
+            let (name, country) = (location[..i].trim(), location[i + 1..].trim());
+            if country.len() == 2 {
+                places = find(self.cfg, name, Some(country))?;
+            }
+        }
+        if places.is_empty() {
+            places = find(self.cfg, &location, None)?;
+        }
+        trace!(log, "GeoNames results: {:?}", places);
+
+        Ok(Ok(places
+            .into_iter()
+            .enumerate()
+            .map(|(i, g)| Place {
+                latitude: g.latitude,
+                longitude: g.longitude,
+                label: format!("{}, {}", g.name, g.country_code),
+                // The most populous place of a name is most likely meant
+                relevance: 1.0 - i as f64 / 10.0,
+                populated: true,
+            })
+            .collect()))
+    }
+}
+
+// The places called `name`, most populous first
+fn find(cfg: &ServerCfg, name: &str, country: Option<&str>) -> Result<Vec<models::Geoname>> {
+    with_database(cfg, |db| {
+        let ids = n_dsl::geoname_names
+            .filter(n_dsl::name.eq(name))
+            .select(n_dsl::geoname_id);
+        let query = g_dsl::geonames.filter(g_dsl::id.eq_any(ids)).into_boxed();
+        let query = if let Some(country) = country {
+            query.filter(g_dsl::country_code.eq(country.to_uppercase()))
+        } else {
+            query
+        };
+        Ok(query
+            .order(g_dsl::population.desc())
+            .limit(5)
+            .load::<models::Geoname>(db)?)
+    })
+}
+
+/// Imports the GeoNames dump at `path`, e.g. `cities15000.txt`, unless the database already has
+/// GeoNames data
+pub fn import(cfg: &ServerCfg, log: &Logger, path: &str) -> Result<()> {
+    let imported = with_database(cfg, |db| {
+        Ok(g_dsl::geonames.count().get_result::<i64>(db)?)
+    })?;
+    if imported != 0 {
+        debug!(log, "{} GeoNames places already imported", imported);
+        return Ok(());
+    }
+
+    info!(log, "Importing GeoNames from {}", path);
+    let count = with_database(cfg, |db| {
+        db.transaction(|| {
+            let mut count = 0;
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                // Tab separated, see http://download.geonames.org/export/dump/readme.txt
+                let fields = line.split('\t').collect::<Vec<_>>();
+                let parsed = if fields.len() >= 15 {
+                    (
+                        fields[0].parse(),
+                        fields[4].parse(),
+                        fields[5].parse(),
+                        fields[14].parse(),
+                    )
+                } else {
+                    debug!(log, "Skipping malformed GeoNames line: {}", line);
+                    continue;
+                };
+                let (id, latitude, longitude, population) = match parsed {
+                    (Ok(id), Ok(lat), Ok(lng), Ok(population)) => (id, lat, lng, population),
+                    _ => {
+                        debug!(log, "Skipping malformed GeoNames line: {}", line);
+                        continue;
+                    }
+                };
+                diesel::insert_into(schema::geonames::table)
+                    .values(&models::NewGeoname {
+                        id: id,
+                        name: fields[1],
+                        country_code: fields[8],
+                        latitude: latitude,
+                        longitude: longitude,
+                        population: population,
+                    })
+                    .execute(db)?;
+
+                // The name, its ASCII version, and the comma separated alternate names
+                let mut names = fields[3]
+                    .split(',')
+                    .chain(Some(fields[1]))
+                    .chain(Some(fields[2]))
+                    .map(|n| n.trim().to_lowercase())
+                    .filter(|n| !n.is_empty())
+                    .collect::<Vec<_>>();
+                names.sort();
+                names.dedup();
+                let names = names
+                    .iter()
+                    .map(|n| models::NewGeonameName {
+                        name: n,
+                        geoname_id: id,
+                    })
+                    .collect::<Vec<_>>();
+                diesel::insert_into(schema::geoname_names::table)
+                    .values(&names)
+                    .execute(db)?;
+                count += 1;
+            }
+            Ok(count)
+        })
+    })?;
+    info!(log, "Imported {} GeoNames places", count);
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use reqwest::Client;
+use serde_json::Value;
+use slog::Logger;
+
+use errors::*;
+use super::{label, Geocoder, Place};
+
+pub const API_BASE: &str = "http://www.mapquestapi.com";
+
+pub struct MapQuest {
+    pub base: String,
+    pub key: String,
+}
+
+impl Geocoder for MapQuest {
+    fn geocode(
+        &self,
+        client: &Client,
+        log: &Logger,
+        location: &str,
+    ) -> Result<::std::result::Result<Vec<Place>, String>> {
+        let json: Value = client
+            .get(&format!("{}/geocoding/v1/address", self.base))
+            .query(&[("key", &*self.key), ("location", location), ("maxResults", "5")])
+            .send()?
+            .json()?;
+
+        let status = json.pointer("/info/statuscode").and_then(Value::as_u64);
+        let messages = json.pointer("/info/messages");
+        if status == Some(403) {
+            crit!(
+                log,
+                "Geocoding API key probably reached max quota: {:?}",
+                messages
+            );
+            return Ok(Err("Sorry, the geocoding API key seems to have reached its max \
+                           qouta; It resets each month."
+                .to_owned()));
+        } else if status != Some(0) {
+            crit!(log, "Geocoding request failed: {:?}", messages);
+            return Ok(Err("Sorry, looking up the location failed.".to_owned()));
+        }
+
+        let locations = json.pointer("/results/0/locations")
+            .and_then(Value::as_array)
+            .map(|l| &l[..])
+            .unwrap_or(&[]);
+        Ok(Ok(locations
+            .iter()
+            .enumerate()
+            .filter_map(|(i, l)| {
+                // Unknown areas are empty strings
+                let area = |n: u8| {
+                    l.get(&format!("adminArea{}", n))
+                        .and_then(Value::as_str)
+                        .filter(|a| !a.is_empty())
+                };
+                trace!(log, "Geocode quality: {:?}", l.get("geocodeQualityCode"));
+                Some(Place {
+                    latitude: l.pointer("/latLng/lat")?.as_f64()?,
+                    longitude: l.pointer("/latLng/lng")?.as_f64()?,
+                    label: label(&[area(5), area(3).or_else(|| area(4)), area(1)]),
+                    // Results are ordered by relevance
+                    relevance: 1.0 - i as f64 / 10.0,
+                    populated: l.get("geocodeQuality").and_then(Value::as_str) == Some("CITY"),
+                })
+            })
+            .collect()))
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use reqwest::Client;
+use slog::Logger;
+
+use config::{GeocodingService, ServerCfg};
+use errors::*;
+
+pub mod geonames;
+mod mapquest;
+mod nominatim;
+mod photon;
+
+/// A source of coordinates for location names
+pub trait Geocoder {
+    /// Candidates for `location`; failures the user should know about, like an exhausted
+    /// quota, are returned as `Err(reply)`
+    fn geocode(
+        &self,
+        client: &Client,
+        log: &Logger,
+        location: &str,
+    ) -> Result<::std::result::Result<Vec<Place>, String>>;
+}
+
+/// A candidate for a location
+#[derive(Debug, Clone)]
+pub struct Place {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// What is replied with, e.g. `Paris, Île-de-France, France`
+    pub label: String,
+    /// How well it matches, from 0 to 1
+    pub relevance: f64,
+    /// Whether it's a city, town or village
+    pub populated: bool,
+}
+
+// The geocoder of the server
+pub fn geocoder<'a>(cfg: &'a ServerCfg) -> Box<Geocoder + 'a> {
+    let service = cfg.geocoding_service();
+    // Overridable, e.g. to test against a local stand-in
+    let base = |default: &str| {
+        cfg.geocoding_api_bases
+            .as_ref()
+            .and_then(|bases| bases.get(service.name()))
+            .map(|b| b.trim_end_matches('/').to_owned())
+            .unwrap_or_else(|| default.to_owned())
+    };
+    match service {
+        GeocodingService::MapQuest => Box::new(mapquest::MapQuest {
+            base: base(mapquest::API_BASE),
+            key: cfg.geocoding_key.clone().unwrap(),
+        }),
+        GeocodingService::Nominatim => Box::new(nominatim::Nominatim {
+            base: base(nominatim::API_BASE),
+        }),
+        GeocodingService::Photon => Box::new(photon::Photon {
+            base: base(photon::API_BASE),
+        }),
+        GeocodingService::GeoNames => Box::new(geonames::GeoNames { cfg: cfg }),
+    }
+}
+
+/// The best candidate, i.e. the most relevant one, where settlements are preferred, as they are
+/// what's usually asked for. The first one wins ties.
+pub fn best(places: Vec<Place>) -> Option<Place> {
+    let rank = |p: &Place| p.relevance + if p.populated { 0.1 } else { 0.0 };
+    places.into_iter().fold(None, |best: Option<Place>, p| {
+        if best.as_ref().map_or(false, |b| rank(b) >= rank(&p)) {
+            best
+        } else {
+            Some(p)
+        }
+    })
+}
+
+/// Joins the known parts of a label, skipping repeats like `Berlin, Berlin`
+pub fn label(parts: &[Option<&str>]) -> String {
+    let mut label: Vec<&str> = Vec::new();
+    for part in parts.iter().filter_map(|p| *p) {
+        if !part.is_empty() && !label.contains(&part) {
+            label.push(part);
+        }
+    }
+    label.join(", ")
+}
@@ -0,0 +1,88 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use reqwest::Client;
+use reqwest::header::USER_AGENT;
+use serde_json::Value;
+use slog::Logger;
+
+use errors::*;
+use super::{label, Geocoder, Place};
+use super::super::AGENT;
+
+pub const API_BASE: &str = "https://nominatim.openstreetmap.org";
+
+pub struct Nominatim {
+    pub base: String,
+}
+
+impl Geocoder for Nominatim {
+    fn geocode(
+        &self,
+        client: &Client,
+        log: &Logger,
+        location: &str,
+    ) -> Result<::std::result::Result<Vec<Place>, String>> {
+        let json: Value = client
+            .get(&format!("{}/search", self.base))
+            .query(&[
+                ("q", location),
+                ("format", "jsonv2"),
+                ("addressdetails", "1"),
+                ("accept-language", "en"),
+                ("limit", "5"),
+            ])
+            .header(USER_AGENT, AGENT)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        trace!(log, "Nominatim results: {:?}", json);
+
+        let results = json.as_array().map(|r| &r[..]).unwrap_or(&[]);
+        Ok(Ok(results
+            .iter()
+            .filter_map(|r| {
+                let text = |v: Option<&Value>, field: &str| {
+                    v.and_then(|v| v.get(field)).and_then(Value::as_str)
+                };
+                let (r, address) = (Some(r), r.get("address"));
+                let city = ["city", "town", "village", "hamlet", "municipality"]
+                    .iter()
+                    .filter_map(|f| text(address, f))
+                    .next();
+                let kind = text(r, "addresstype").or_else(|| text(r, "type"));
+                Some(Place {
+                    // Coordinates are strings
+                    latitude: text(r, "lat")?.parse().ok()?,
+                    longitude: text(r, "lon")?.parse().ok()?,
+                    label: label(&[
+                        text(r, "name"),
+                        city,
+                        text(address, "state").or_else(|| text(address, "county")),
+                        text(address, "country"),
+                    ]),
+                    relevance: r.and_then(|r| r.get("importance"))
+                        .and_then(Value::as_f64)
+                        .unwrap_or(0.0),
+                    populated: kind.map_or(false, |k| {
+                        ["city", "town", "village", "hamlet"].contains(&k)
+                    }),
+                })
+            })
+            .collect()))
+    }
+}
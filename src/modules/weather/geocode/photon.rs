@@ -0,0 +1,76 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use reqwest::Client;
+use serde_json::Value;
+use slog::Logger;
+
+use errors::*;
+use super::{label, Geocoder, Place};
+
+pub const API_BASE: &str = "https://photon.komoot.io";
+
+pub struct Photon {
+    pub base: String,
+}
+
+impl Geocoder for Photon {
+    fn geocode(
+        &self,
+        client: &Client,
+        log: &Logger,
+        location: &str,
+    ) -> Result<::std::result::Result<Vec<Place>, String>> {
+        let json: Value = client
+            .get(&format!("{}/api", self.base))
+            .query(&[("q", location), ("lang", "en"), ("limit", "5")])
+            .send()?
+            .error_for_status()?
+            .json()?;
+        trace!(log, "Photon results: {:?}", json);
+
+        let features = json.get("features")
+            .and_then(Value::as_array)
+            .map(|f| &f[..])
+            .unwrap_or(&[]);
+        Ok(Ok(features
+            .iter()
+            .enumerate()
+            .filter_map(|(i, f)| {
+                let properties = f.get("properties")?;
+                let text = |field: &str| properties.get(field).and_then(Value::as_str);
+                // GeoJSON coordinates are longitude first
+                let coordinates = f.pointer("/geometry/coordinates")?;
+                Some(Place {
+                    latitude: coordinates.get(1)?.as_f64()?,
+                    longitude: coordinates.get(0)?.as_f64()?,
+                    label: label(&[
+                        text("name"),
+                        text("city"),
+                        text("state").or_else(|| text("county")),
+                        text("country"),
+                    ]),
+                    // Results are ordered by relevance
+                    relevance: 1.0 - i as f64 / 10.0,
+                    populated: text("type") == Some("city")
+                        || ["city", "town", "village", "hamlet"]
+                            .contains(&text("osm_value").unwrap_or("")),
+                })
+            })
+            .collect()))
+    }
+}
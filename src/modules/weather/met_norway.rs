@@ -0,0 +1,229 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use chrono::prelude::*;
+use reqwest::Client;
+use reqwest::header::USER_AGENT;
+use serde_json::Value;
+use slog::Logger;
+
+use errors::*;
+use super::{approximate_offset, number, DataPoint, Forecast, Span, WeatherProvider, AGENT};
+
+pub const API_BASE: &str = "https://api.met.no";
+
+pub struct MetNorway {
+    pub base: String,
+}
+
+impl WeatherProvider for MetNorway {
+    fn limits(&self) -> (usize, usize) {
+        (48, 8)
+    }
+
+    fn forecast(
+        &self,
+        client: &Client,
+        log: &Logger,
+        latitude: f64,
+        longitude: f64,
+        span: Span,
+    ) -> Result<Forecast> {
+        // More than 4 decimals are rejected
+        let json: Value = client
+            .get(&format!(
+                "{}/weatherapi/locationforecast/2.0/complete",
+                self.base
+            ))
+            .query(&[
+                ("lat", format!("{:.4}", latitude)),
+                ("lon", format!("{:.4}", longitude)),
+            ])
+            .header(USER_AGENT, AGENT)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        trace!(log, "MET Norway forecast: {:?}", json);
+
+        // There is no timezone, so days are approximated by the longitude
+        let offset = approximate_offset(longitude);
+        let series = json.pointer("/properties/timeseries")
+            .and_then(Value::as_array)
+            .ok_or(Error::NoExtractableData)?
+            .iter()
+            .filter_map(|entry| {
+                let time = entry.get("time")?.as_str()?.parse::<DateTime<Utc>>().ok()?;
+                Some((time.with_timezone(&offset), entry.get("data")?))
+            })
+            .collect::<Vec<_>>();
+
+        // The series is hourly for the next days, then 6-hourly
+        let mut forecast = Forecast::default();
+        match span {
+            Span::Current => {
+                forecast.currently = series.first().map(|&(_, data)| data_point(data));
+            }
+            Span::Hours(n) => {
+                forecast.hourly = series
+                    .iter()
+                    .take_while(|&&(_, data)| data.get("next_1_hours").is_some())
+                    .take(n)
+                    .map(|&(_, data)| data_point(data))
+                    .collect();
+            }
+            Span::Days(n) => {
+                let mut days: Vec<Vec<(DateTime<FixedOffset>, &Value)>> = Vec::new();
+                for &(time, data) in &series {
+                    if days.last().map_or(true, |d| d[0].0.date() != time.date()) {
+                        if days.len() == n {
+                            break;
+                        }
+                        days.push(Vec::new());
+                    }
+                    days.last_mut().unwrap().push((time, data));
+                }
+                forecast.daily = days.iter().map(|d| day(d)).collect();
+            }
+        }
+        Ok(forecast)
+    }
+}
+
+fn data_point(data: &Value) -> DataPoint {
+    let instant = data.pointer("/instant/details");
+    let next = data.get("next_1_hours").or_else(|| data.get("next_6_hours"));
+    let symbol = next.and_then(|n| n.pointer("/summary/symbol_code"))
+        .and_then(Value::as_str);
+    let percent = |details: Option<&Value>, field: &str| {
+        details.and_then(|d| number(d, field)).map(|p| p / 100.0)
+    };
+    DataPoint {
+        summary: symbol.map(describe),
+        temperature: instant.and_then(|i| number(i, "air_temperature")),
+        cloud_cover: percent(instant, "cloud_area_fraction"),
+        humidity: percent(instant, "relative_humidity"),
+        precip_probability: percent(
+            next.and_then(|n| n.get("details")),
+            "probability_of_precipitation",
+        ),
+        precip_type: symbol.and_then(precip_type).map(str::to_owned),
+        precip_amount: next.and_then(|n| n.get("details"))
+            .and_then(|d| number(d, "precipitation_amount")),
+        // m/s
+        wind_speed: instant
+            .and_then(|i| number(i, "wind_speed"))
+            .map(|s| s * 3.6),
+        wind_bearing: instant.and_then(|i| number(i, "wind_from_direction")),
+        pressure: instant.and_then(|i| number(i, "air_pressure_at_sea_level")),
+        uv_index: instant.and_then(|i| number(i, "ultraviolet_index_clear_sky")),
+        dew_point: instant.and_then(|i| number(i, "dew_point_temperature")),
+        ..Default::default()
+    }
+}
+
+// Summarizes the entries of a day, the summary is the one closest to noon
+fn day(entries: &[(DateTime<FixedOffset>, &Value)]) -> DataPoint {
+    let points = entries
+        .iter()
+        .map(|&(_, data)| data_point(data))
+        .collect::<Vec<_>>();
+    let noon = entries
+        .iter()
+        .enumerate()
+        .min_by_key(|&(_, &(time, _))| (time.hour() as i32 - 12).abs())
+        .map_or(0, |(i, _)| i);
+    let values = |f: &Fn(&DataPoint) -> Option<f64>| {
+        points.iter().filter_map(|p| f(p)).collect::<Vec<_>>()
+    };
+
+    let temperatures = values(&|p| p.temperature);
+    DataPoint {
+        summary: points[noon].summary.clone(),
+        temperature_min: min(&temperatures),
+        temperature_max: max(&temperatures),
+        cloud_cover: mean(&values(&|p| p.cloud_cover)),
+        humidity: mean(&values(&|p| p.humidity)),
+        precip_probability: max(&values(&|p| p.precip_probability)),
+        precip_type: points[noon].precip_type.clone(),
+        // Each entry has the amount until the next one
+        precip_amount: sum(&values(&|p| p.precip_amount)),
+        wind_speed: max(&values(&|p| p.wind_speed)),
+        wind_bearing: points[noon].wind_bearing,
+        pressure: mean(&values(&|p| p.pressure)),
+        uv_index: max(&values(&|p| p.uv_index)),
+        dew_point: mean(&values(&|p| p.dew_point)),
+        ..Default::default()
+    }
+}
+
+fn min(values: &[f64]) -> Option<f64> {
+    values.iter().cloned().fold(None, |acc, v| Some(acc.map_or(v, |a: f64| a.min(v))))
+}
+
+fn max(values: &[f64]) -> Option<f64> {
+    values.iter().cloned().fold(None, |acc, v| Some(acc.map_or(v, |a: f64| a.max(v))))
+}
+
+fn sum(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum())
+    }
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
+
+// Symbol codes look like `lightrainshowers_day`
+fn describe(symbol: &str) -> String {
+    const WORDS: &[&str] = &[
+        "clear", "sky", "fair", "partly", "cloudy", "fog", "light", "heavy", "rain", "sleet",
+        "snow", "showers", "and", "thunder",
+    ];
+    // Misspelled in the API
+    let symbol = symbol.replace("lightssleet", "lightsleet");
+    let mut rest = symbol.split('_').next().unwrap();
+    let mut words = Vec::new();
+    while !rest.is_empty() {
+        if let Some(word) = WORDS.iter().find(|w| rest.starts_with(*w)) {
+            words.push(*word);
+            rest = &rest[word.len()..];
+        } else {
+            words.push(rest);
+            break;
+        }
+    }
+    words.join(" ")
+}
+
+fn precip_type(symbol: &str) -> Option<&'static str> {
+    if symbol.contains("snow") {
+        Some("snow")
+    } else if symbol.contains("sleet") {
+        Some("sleet")
+    } else if symbol.contains("rain") {
+        Some("rain")
+    } else {
+        None
+    }
+}
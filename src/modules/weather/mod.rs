@@ -0,0 +1,1181 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use chrono::Duration;
+use chrono::prelude::*;
+use chrono_tz::Tz;
+use crossbeam;
+use diesel;
+use diesel::prelude::*;
+use parking_lot::RwLock;
+use regex::Regex;
+use reqwest::Client;
+use serde_json::Value;
+use slog::Logger;
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use config::{Config, ServerCfg, Units, WeatherService};
+use errors::*;
+use models;
+use super::format;
+use super::prefs;
+use schema;
+use schema::location_cache::dsl as lc_dsl;
+use schema::geocode_cache::dsl as gc_dsl;
+use super::queue::Priority;
+use self::astro::Daylight;
+
+// Optional fields of replies, e.g. `.weather +sun +uv Berlin`
+const FIELDS: &[&str] = &[
+    "sun",
+    "moon",
+    "uv",
+    "pressure",
+    "visibility",
+    "dewpoint",
+    "wind",
+];
+
+// How many locations `.weather a vs b` may compare
+const MAX_COMPARED: usize = 4;
+
+// Some APIs require identifying the client, to be able to contact its user
+const AGENT: &str = concat!(
+    "parabot/",
+    env!("CARGO_PKG_VERSION"),
+    " https://github.com/ParadoxSpiral/parabot"
+);
+
+mod astro;
+mod dark_sky;
+mod geocode;
+mod met_norway;
+mod open_meteo;
+mod openweathermap;
+mod units;
+mod when;
+
+lazy_static!{
+    static ref LOCATION_CACHE: RwLock<HashMap<(String, String), String>> = {
+        RwLock::new(HashMap::new())
+    };
+    // location -> (lat, lng, reverse location, geocoder)
+    static ref GEOCODING_CACHE: RwLock<HashMap<String, (f32, f32, String, String)>> = {
+        RwLock::new(HashMap::new())
+    };
+}
+
+/// A source of forecasts
+pub trait WeatherProvider {
+    /// How many hours and days ahead forecasts are available
+    fn limits(&self) -> (usize, usize);
+
+    /// The weather at a location; only the data `span` asks for needs to be filled in
+    fn forecast(
+        &self,
+        client: &Client,
+        log: &Logger,
+        latitude: f64,
+        longitude: f64,
+        span: Span,
+    ) -> Result<Forecast>;
+}
+
+/// What a forecast is requested for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Span {
+    Current,
+    /// The next n hours, starting with the current one
+    Hours(usize),
+    /// The next n days, starting with today
+    Days(usize),
+}
+
+#[derive(Debug, Default)]
+pub struct Forecast {
+    /// Of the location, if the provider knows it
+    pub timezone: Option<Tz>,
+    pub currently: Option<DataPoint>,
+    pub hourly: Vec<DataPoint>,
+    pub daily: Vec<DataPoint>,
+    pub alerts: Vec<Alert>,
+}
+
+/// The weather at one point in time, or of one day. Temperatures are in °C, speeds in km/h,
+/// and fractions between 0 and 1.
+#[derive(Debug, Default, Clone)]
+pub struct DataPoint {
+    pub summary: Option<String>,
+    pub temperature: Option<f64>,
+    pub apparent_temperature: Option<f64>,
+    pub temperature_min: Option<f64>,
+    pub temperature_max: Option<f64>,
+    pub apparent_temperature_min: Option<f64>,
+    pub apparent_temperature_max: Option<f64>,
+    pub cloud_cover: Option<f64>,
+    pub humidity: Option<f64>,
+    pub precip_probability: Option<f64>,
+    pub precip_type: Option<String>,
+    /// mm in the hour or day
+    pub precip_amount: Option<f64>,
+    pub wind_speed: Option<f64>,
+    /// Degrees, where the wind comes from
+    pub wind_bearing: Option<f64>,
+    /// hPa at sea level
+    pub pressure: Option<f64>,
+    pub uv_index: Option<f64>,
+    /// km
+    pub visibility: Option<f64>,
+    pub dew_point: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct Alert {
+    pub title: String,
+    pub regions: Vec<String>,
+    pub description: String,
+    /// Unix timestamp
+    pub expires: i64,
+}
+
+// The provider used in `target`
+fn provider(cfg: &ServerCfg, target: &str) -> Box<WeatherProvider> {
+    let service = cfg.weather_service(target);
+    // Overridable, e.g. to test against a local stand-in
+    let base = |default: &str| {
+        cfg.weather_api_bases
+            .as_ref()
+            .and_then(|bases| bases.get(service.name()))
+            .map(|b| b.trim_end_matches('/').to_owned())
+            .unwrap_or_else(|| default.to_owned())
+    };
+    match service {
+        WeatherService::DarkSky => Box::new(dark_sky::DarkSky {
+            base: base(dark_sky::API_BASE),
+            secret: cfg.weather_secret.clone().unwrap(),
+        }),
+        WeatherService::OpenMeteo => Box::new(open_meteo::OpenMeteo {
+            base: base(open_meteo::API_BASE),
+        }),
+        WeatherService::OpenWeatherMap => Box::new(openweathermap::OpenWeatherMap {
+            base: base(openweathermap::API_BASE),
+            key: cfg.openweathermap_api_key.clone().unwrap(),
+        }),
+        WeatherService::MetNorway => Box::new(met_norway::MetNorway {
+            base: base(met_norway::API_BASE),
+        }),
+    }
+}
+
+// The UTC offset of a location, without a timezone database the sun's is close enough
+fn approximate_offset(longitude: f64) -> FixedOffset {
+    FixedOffset::east((longitude / 15.0).round() as i32 * 3600)
+}
+
+// A number field of a JSON object
+fn number(json: &Value, field: &str) -> Option<f64> {
+    json.get(field).and_then(Value::as_f64)
+}
+
+// The units of the user, or else the channel's, metric by default
+fn units(cfg: &ServerCfg, nick: &str, target: &str) -> Units {
+    prefs::get(cfg, nick, "units")
+        .and_then(|u| Units::parse(&u))
+        .or_else(|| {
+            cfg.channels
+                .iter()
+                .find(|c| c.name == target)
+                .and_then(|c| c.weather_units)
+        })
+        .unwrap_or(Units::Metric)
+}
+
+// What's needed for the optional fields
+struct Extras<'a> {
+    fields: &'a [String],
+    latitude: f64,
+    longitude: f64,
+    offset: FixedOffset,
+}
+
+fn format_data_point(
+    out: &mut String,
+    dp: &DataPoint,
+    days: bool,
+    units: Units,
+    extras: &Extras,
+    time: DateTime<Utc>,
+) {
+    if let Some(ref s) = dp.summary {
+        out.push_str(&format!("{}: ", s.to_lowercase()));
+    }
+    let mut parts = Vec::new();
+    let both = |a: Option<f64>, b: Option<f64>| a.and_then(|a| b.map(|b| (a, b)));
+    if days {
+        if let Some((tmi, tma)) = both(dp.apparent_temperature_min, dp.apparent_temperature_max)
+            .or_else(|| both(dp.temperature_min, dp.temperature_max))
+        {
+            parts.push(format!(
+                "{}{}",
+                format::bold(format!(
+                    "{}…{}",
+                    units.temperature(tmi),
+                    units.temperature(tma)
+                )),
+                units.temperature_label()
+            ));
+        }
+    } else if let Some(t) = dp.apparent_temperature.or(dp.temperature) {
+        parts.push(format!(
+            "{}{}",
+            format::bold(units.temperature(t)),
+            units.temperature_label()
+        ));
+    }
+    if let Some(cc) = dp.cloud_cover {
+        if let Some(h) = dp.humidity {
+            parts.push(format!(
+                "{}% cloud cover, {}% humidity",
+                (cc * 100f64).round(),
+                (h * 100f64).round()
+            ));
+        } else {
+            parts.push(format!("{}% cloud cover", (cc * 100f64).round()));
+        }
+    }
+    if let Some(pp) = dp.precip_probability {
+        if pp > 0.049f64 {
+            let precip_type = dp.precip_type
+                .as_ref()
+                .map(|t| t.to_lowercase())
+                .unwrap_or_else(|| "precipitation".to_owned());
+            let amount = dp.precip_amount
+                .filter(|a| *a > 0.0)
+                .map(|a| format!(" ({})", units.precipitation(a)))
+                .unwrap_or_default();
+            parts.push(format!(
+                "{}% chance of {}{}",
+                (pp * 100f64).round(),
+                precip_type,
+                amount
+            ));
+        }
+    }
+    if let Some(ws) = dp.wind_speed {
+        let direction = dp.wind_bearing
+            .filter(|_| extras.fields.iter().any(|f| f == "wind"))
+            .map(|b| format!(" from the {}", compass_point(b)))
+            .unwrap_or_default();
+        parts.push(format!("{} wind speed{}", units.speed(ws), direction));
+    }
+    for field in extras.fields {
+        parts.extend(match &**field {
+            "sun" => Some(sun(extras, time)),
+            "moon" => Some(moon(time)),
+            "uv" => dp.uv_index.map(|uv| format!("UV index {}", uv.round())),
+            "pressure" => dp.pressure
+                .map(|p| format!("{} pressure", units.pressure(p))),
+            "visibility" => dp.visibility
+                .map(|v| format!("{} visibility", units.distance(v))),
+            "dewpoint" => dp.dew_point.map(|d| {
+                format!(
+                    "{}{} dew point",
+                    units.temperature(d),
+                    units.temperature_label()
+                )
+            }),
+            // The wind's direction is shown with its speed
+            _ => None,
+        });
+    }
+    out.push_str(&parts.join("; "));
+}
+
+// Sunrise and sunset on the local day of `time`
+fn sun(extras: &Extras, time: DateTime<Utc>) -> String {
+    let date = time.with_timezone(&extras.offset).date().naive_local();
+    match astro::daylight(extras.latitude, extras.longitude, date) {
+        Daylight::Between(rise, set) => format!(
+            "sunrise {}, sunset {}",
+            rise.with_timezone(&extras.offset).format("%H:%M"),
+            set.with_timezone(&extras.offset).format("%H:%M")
+        ),
+        Daylight::PolarDay => "polar day".to_owned(),
+        Daylight::PolarNight => "polar night".to_owned(),
+    }
+}
+
+fn moon(time: DateTime<Utc>) -> String {
+    let phase = astro::moon_phase(time);
+    format!(
+        "moon: {}, {}% illuminated",
+        astro::phase_name(phase),
+        (astro::illumination(phase) * 100.0).round()
+    )
+}
+
+// E.g. `NNW` for 337.5°
+fn compass_point(bearing: f64) -> &'static str {
+    const POINTS: &[&str] = &[
+        "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW",
+        "NW", "NNW",
+    ];
+    POINTS[(bearing / 22.5).round() as usize % POINTS.len()]
+}
+
+/// `.astro [location]` replies with the length of the day and the moon phase at a location
+pub fn astro(
+    cfg: &ServerCfg,
+    log: &Logger,
+    msg: &str,
+    nick: &str,
+    prefix: &str,
+) -> Result<String> {
+    let location = if msg.trim().is_empty() {
+        let cached = LOCATION_CACHE
+            .read()
+            .get(&(cfg.address.clone(), nick.to_owned()))
+            .cloned();
+        if let Some(cached) = cached {
+            cached
+        } else {
+            return Ok(format!(
+                "Invalid `{0}astro` syntax, try: `{0}astro <location>`",
+                prefix
+            ));
+        }
+    } else {
+        msg.trim().to_owned()
+    };
+    let (latitude, longitude, reverse_location) =
+        match geocode(cfg, &Client::new(), log, &location)? {
+            Ok(geocode) => geocode,
+            Err(reply) => return Ok(reply),
+        };
+
+    // There's no timezone without asking a weather provider
+    let (latitude, longitude) = (f64::from(latitude), f64::from(longitude));
+    let offset = approximate_offset(longitude);
+    let now = Utc::now();
+    let today = now.with_timezone(&offset).date().naive_local();
+    let daylight = match astro::daylight(latitude, longitude, today) {
+        Daylight::Between(rise, set) => {
+            let length = set.signed_duration_since(rise);
+            format!(
+                "sunrise {}, sunset {}, {}h {}m of daylight",
+                rise.with_timezone(&offset).format("%H:%M"),
+                set.with_timezone(&offset).format("%H:%M"),
+                length.num_hours(),
+                length.num_minutes() % 60
+            )
+        }
+        Daylight::PolarDay => "polar day, the sun doesn't set".to_owned(),
+        Daylight::PolarNight => "polar night, the sun doesn't rise".to_owned(),
+    };
+    Ok(format!(
+        "In {} (UTC{}): {}; {}",
+        reverse_location,
+        offset,
+        daylight,
+        moon(now)
+    ))
+}
+
+// Geocodes `location` from the cache, or else looks it up
+fn geocode(
+    cfg: &ServerCfg,
+    client: &Client,
+    log: &Logger,
+    location: &str,
+) -> Result<::std::result::Result<(f32, f32, String), String>> {
+    // Results of another geocoder are replaced, e.g. after switching `geocoder`
+    let provider = cfg.geocoding_service().name();
+    let cached = GEOCODING_CACHE
+        .read()
+        .get(&location.to_lowercase())
+        .filter(|&&(_, _, _, ref p)| p == provider)
+        .cloned();
+    if let Some((lat, lng, revl, _)) = cached {
+        trace!(
+            log,
+            "Got geocode from cache: lat: {}; lng: {}, revl: {}",
+            lat,
+            lng,
+            revl
+        );
+        Ok(Ok((lat, lng, revl)))
+    } else {
+        lookup(cfg, client, log, location)
+    }
+}
+
+// Geocodes `location` and caches the result, or returns a reply why it failed
+fn lookup(
+    cfg: &ServerCfg,
+    client: &Client,
+    log: &Logger,
+    location: &str,
+) -> Result<::std::result::Result<(f32, f32, String), String>> {
+    let places = match geocode::geocoder(cfg).geocode(client, log, location)? {
+        Ok(places) => places,
+        Err(reply) => return Ok(Err(reply)),
+    };
+    let place = if let Some(place) = geocode::best(places) {
+        place
+    } else {
+        debug!(log, "Location not found: {}", location);
+        return Ok(Err(format!("Sorry, I couldn't find {}.", location)));
+    };
+    let provider = cfg.geocoding_service().name();
+    trace!(log, "Got geocode from {}: {:?}", provider, place);
+
+    let (lat, lng) = (place.latitude as f32, place.longitude as f32);
+    GEOCODING_CACHE
+        .write()
+        .insert(
+            location.to_lowercase(),
+            (lat, lng, place.label.clone(), provider.to_owned()),
+        );
+    let new = models::NewGeocode {
+        location: &location.to_lowercase(),
+        latitude: lat,
+        longitude: lng,
+        reverse_location: &place.label,
+        provider: provider,
+    };
+    super::with_database(cfg, |db| {
+        diesel::replace_into(schema::geocode_cache::table)
+            .values(&new)
+            .execute(db)?;
+        Ok(())
+    })?;
+    Ok(Ok((lat, lng, place.label)))
+}
+
+// Read DB to get init values
+pub fn init(cfg: &Config, log: &Logger) -> Result<()> {
+    let mut lc = LOCATION_CACHE.write();
+    let mut gc = GEOCODING_CACHE.write();
+    for srv in &cfg.servers {
+        if let Some(ref path) = srv.geonames_file {
+            geocode::geonames::import(srv, log, path)?;
+        }
+        let (locations, geocodes) = super::with_database(srv, |db| {
+            Ok((
+                lc_dsl::location_cache
+                    .filter(lc_dsl::server.eq(&srv.address))
+                    .load::<models::Location>(db)?,
+                gc_dsl::geocode_cache.load::<models::Geocode>(db)?,
+            ))
+        })?;
+
+        info!(log, "Location cache: {:?}", &locations);
+        for q in locations {
+            lc.insert((srv.address.clone(), q.nick.clone()), q.location);
+        }
+        lc.shrink_to_fit();
+        info!(log, "Geocode cache: {:?}", &geocodes);
+        for g in geocodes {
+            gc.insert(
+                g.location.clone(),
+                (g.latitude, g.longitude, g.reverse_location, g.provider),
+            );
+        }
+        gc.shrink_to_fit();
+    }
+    Ok(())
+}
+
+// `[<n|x-y><d|h>] [location]`, returns the range, whether it's of hours or days, and the location
+fn parse_range(msg: &str) -> Option<(Range<usize>, bool, bool, Option<&str>)> {
+    // Only compile the regex once
+    lazy_static! {
+        static ref REGEX: Regex = Regex::new("\
+            \\s{0,}\
+            (?:\
+                (?:(?:(?P<range_x>\\d+)-(?P<range_y>\\d+))\
+                     |(?P<digits>\\d+))\
+                \\s{0,}\
+                (?:(?P<h>h)|(?P<d>d))\
+                \\s{0,}\
+                (?P<inner_location>.+){0,1}\
+            )|\
+            (?P<outer_location>.+)").unwrap();
+    }
+
+    let captures = REGEX.captures(msg)?;
+    // Too large numbers don't parse
+    let number = |s: &str| s.parse::<usize>().ok();
+    let range = if let Some(d) = captures.name("digits") {
+        let n = number(d.as_str())?;
+        n..n.checked_add(1)?
+    } else if let (Some(x), Some(y)) = (captures.name("range_x"), captures.name("range_y")) {
+        let (x, y) = (number(x.as_str())?, number(y.as_str())?);
+        // e.g. `5-3d`
+        if x > y {
+            return None;
+        }
+        x..y.checked_add(1)?
+    } else {
+        0..1
+    };
+    let location = captures
+        .name("inner_location")
+        .or_else(|| captures.name("outer_location"))
+        .map(|l| l.as_str().trim())
+        .filter(|l| !l.is_empty());
+    Some((
+        range,
+        captures.name("h").is_some(),
+        captures.name("d").is_some(),
+        location,
+    ))
+}
+
+// A location's forecast, and the range of it that was asked for
+struct Fetched {
+    location: String,
+    latitude: f64,
+    longitude: f64,
+    forecast: Forecast,
+    offset: FixedOffset,
+    range: Range<usize>,
+    hours: bool,
+    days: bool,
+}
+
+// Geocodes `location` and fetches its forecast of the asked range, or of the phrase, which is
+// resolved in the location's time
+fn fetch(
+    cfg: &ServerCfg,
+    client: &Client,
+    log: &Logger,
+    provider: &WeatherProvider,
+    location: &str,
+    asked: (Range<usize>, bool, bool),
+    phrase: Option<when::Phrase>,
+) -> Result<::std::result::Result<Fetched, String>> {
+    let (latitude, longitude, reverse_location) = match geocode(cfg, client, log, location)? {
+        Ok(geocode) => geocode,
+        Err(reply) => return Ok(Err(reply)),
+    };
+    let (latitude, longitude) = (f64::from(latitude), f64::from(longitude));
+
+    // Phrases depend on the local time, which is only approximated before the forecast is known
+    let (max_hours, max_days) = provider.limits();
+    let (range, hours, days) = match phrase {
+        Some(phrase) => {
+            let now = Utc::now().with_timezone(&approximate_offset(longitude));
+            when::resolve(phrase, now)
+        }
+        None => asked.clone(),
+    };
+    if phrase.is_some() && (range.end > max_hours && hours || range.end > max_days && days) {
+        return Ok(Err(format!(
+            "Weather data is only available for the next {}h or {}d.",
+            max_hours, max_days
+        )));
+    }
+    let span = if hours {
+        // Leave room for the difference of the approximated and the actual offset
+        Span::Hours(if phrase.is_some() {
+            (range.end + 4).min(max_hours)
+        } else {
+            range.end
+        })
+    } else if days {
+        Span::Days(if phrase.is_some() {
+            (range.end + 1).min(max_days)
+        } else {
+            range.end
+        })
+    } else {
+        Span::Current
+    };
+    let forecast = provider.forecast(client, log, latitude, longitude, span)?;
+
+    let offset = forecast.timezone.map_or_else(
+        || approximate_offset(longitude),
+        |tz| Utc::now().with_timezone(&tz).offset().fix(),
+    );
+    let (range, hours, days) = match phrase {
+        Some(phrase) => when::resolve(phrase, Utc::now().with_timezone(&offset)),
+        None => asked,
+    };
+
+    Ok(Ok(Fetched {
+        location: reverse_location,
+        latitude: latitude,
+        longitude: longitude,
+        forecast: forecast,
+        offset: offset,
+        range: range,
+        hours: hours,
+        days: days,
+    }))
+}
+
+// The locations of e.g. `Berlin vs Tokyo`
+fn compared(location: &str) -> Vec<String> {
+    let mut locations = vec![Vec::new()];
+    for word in location.split_whitespace() {
+        if word.eq_ignore_ascii_case("vs") || word.eq_ignore_ascii_case("vs.") {
+            locations.push(Vec::new());
+        } else {
+            locations.last_mut().unwrap().push(word);
+        }
+    }
+    locations.into_iter().map(|words| words.join(" ")).collect()
+}
+
+// Replies with the weather of several locations side by side, fetched in parallel
+fn compare(
+    cfg: &ServerCfg,
+    log: &Logger,
+    locations: &[String],
+    asked: (Range<usize>, bool, bool),
+    phrase: Option<when::Phrase>,
+    units: Units,
+    target: &str,
+) -> Result<String> {
+    if locations.iter().any(|l| l.is_empty()) {
+        return Ok("Please name a location on each side of `vs`.".to_owned());
+    } else if locations.len() > MAX_COMPARED {
+        return Ok(format!(
+            "Sorry, only up to {} locations can be compared.",
+            MAX_COMPARED
+        ));
+    }
+
+    let client = Client::new();
+    let results = crossbeam::scope(|scope| {
+        let handles = locations
+            .iter()
+            .map(|location| {
+                let client = &client;
+                let asked = asked.clone();
+                scope.spawn(move |_| {
+                    let provider = provider(cfg, target);
+                    fetch(cfg, client, log, &*provider, location, asked, phrase)
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    }).unwrap();
+
+    let mut header = None;
+    let mut summaries = Vec::with_capacity(results.len());
+    for result in results {
+        let fetched = match result? {
+            Ok(fetched) => fetched,
+            Err(reply) => return Ok(reply),
+        };
+        let (range, days) = (&fetched.range, fetched.days);
+        let single = range.start == range.end.saturating_sub(1);
+        header.get_or_insert_with(|| {
+            let unit = if days { "d" } else { "h" };
+            if single && range.start == 0 {
+                if days {
+                    "Today's weather".to_owned()
+                } else {
+                    "Current weather".to_owned()
+                }
+            } else if single {
+                format!("Weather in {}{}", range.start, unit)
+            } else {
+                format!("Weather in the next {}-{}{}", range.start, range.end - 1, unit)
+            }
+        });
+
+        let data = if days {
+            &fetched.forecast.daily
+        } else {
+            &fetched.forecast.hourly
+        };
+        let summary = if single && range.start == 0 && !days {
+            fetched
+                .forecast
+                .currently
+                .as_ref()
+                .or_else(|| fetched.forecast.hourly.first())
+                .map(|dp| compact(dp, days, units))
+        } else if data.len() <= range.start {
+            None
+        } else if single {
+            Some(compact(&data[range.start], days, units))
+        } else {
+            let last = (range.end - 1).min(data.len() - 1);
+            Some(
+                data[range.start..last + 1]
+                    .iter()
+                    .enumerate()
+                    .map(|(n, dp)| format!("{}: {}", n + range.start, compact(dp, days, units)))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            )
+        };
+        summaries.push(format!(
+            "{}: {}",
+            format::bold(fetched.location),
+            summary.unwrap_or_else(|| "no data".to_owned())
+        ));
+    }
+
+    Ok(format!(
+        "{}: {}",
+        header.unwrap_or_default(),
+        summaries.join(" | ")
+    ))
+}
+
+// A data point in few words, for comparisons
+fn compact(dp: &DataPoint, days: bool, units: Units) -> String {
+    let mut parts = Vec::new();
+    let both = |a: Option<f64>, b: Option<f64>| a.and_then(|a| b.map(|b| (a, b)));
+    if days {
+        if let Some((tmi, tma)) = both(dp.apparent_temperature_min, dp.apparent_temperature_max)
+            .or_else(|| both(dp.temperature_min, dp.temperature_max))
+        {
+            parts.push(format!(
+                "{}…{}{}",
+                units.temperature(tmi),
+                units.temperature(tma),
+                units.temperature_label()
+            ));
+        }
+    } else if let Some(t) = dp.apparent_temperature.or(dp.temperature) {
+        parts.push(format!(
+            "{}{}",
+            units.temperature(t),
+            units.temperature_label()
+        ));
+    }
+    if let Some(ref s) = dp.summary {
+        parts.push(s.to_lowercase());
+    }
+    if let Some(pp) = dp.precip_probability.filter(|pp| *pp > 0.049f64) {
+        parts.push(format!(
+            "{}% {}",
+            (pp * 100f64).round(),
+            dp.precip_type
+                .as_ref()
+                .map(|t| t.to_lowercase())
+                .unwrap_or_else(|| "precipitation".to_owned())
+        ));
+    }
+    parts.join(", ")
+}
+
+pub fn handle(
+    cfg: &ServerCfg,
+    log: &Logger,
+    msg: &str,
+    nick: &str,
+    target: &str,
+    prefix: &str,
+) -> Result<String> {
+    // `.weather set units <units>` is a shortcut for `.set units <units>`
+    let trimmed = msg.trim();
+    if trimmed == "set" || trimmed.starts_with("set ") {
+        let args = trimmed[3..].trim();
+        return if args.split(' ').next() == Some("units") {
+            prefs::handle(cfg, log, nick, args, prefix)
+        } else {
+            Ok(format!(
+                "Invalid `{0}weather set` syntax, try: `{0}weather set units \
+                 metric|imperial|uk|si`",
+                prefix
+            ))
+        };
+    }
+    let units = units(cfg, nick, target);
+
+    // Optional fields, in addition to the channel's
+    let mut fields = cfg.channels
+        .iter()
+        .find(|c| c.name == target)
+        .and_then(|c| c.weather_fields.clone())
+        .unwrap_or_default();
+    for word in msg.split_whitespace().filter(|w| w.starts_with('+')) {
+        let field = word[1..].to_lowercase();
+        if !FIELDS.contains(&&*field) {
+            return Ok(format!(
+                "Unknown field `{}`, try one of: {}",
+                word,
+                FIELDS
+                    .iter()
+                    .map(|f| format!("+{}", f))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        } else if !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+    let mut words = msg.split_whitespace()
+        .filter(|w| !w.starts_with('+'))
+        .collect::<Vec<_>>();
+
+    // A time in words, e.g. `tomorrow Tokyo` or `Tokyo this weekend`
+    let phrase = if let Some((phrase, len)) = when::parse(&words) {
+        words.drain(..len);
+        Some(phrase)
+    } else {
+        let n = words.len();
+        (1..n.min(3) + 1)
+            .rev()
+            .filter_map(|len| match when::parse(&words[n - len..]) {
+                Some((phrase, l)) if l == len => Some((phrase, len)),
+                _ => None,
+            })
+            .next()
+            .map(|(phrase, len)| {
+                words.truncate(n - len);
+                phrase
+            })
+    };
+    trace!(log, "Weather phrase: {:?}", phrase);
+    let msg = if words.is_empty() {
+        String::new()
+    } else {
+        format!(" {}", words.join(" "))
+    };
+
+    let provider = provider(cfg, target);
+    let (range, hours, days, location) = {
+        // Use last location
+        if msg.is_empty() {
+            (0..1, false, false, {
+                if let Some(cached) = LOCATION_CACHE
+                    .read()
+                    .get(&(cfg.address.clone(), nick.to_owned()))
+                {
+                    cached.clone()
+                } else {
+                    return Ok(format!(
+                        "You have never used `{0}weather` before, try `{0}help weather`",
+                        prefix
+                    ));
+                }
+            })
+        } else {
+            let (range, h, d, location) = if let Some(parsed) = parse_range(&msg[1..]) {
+                trace!(log, "Weather range: {:?}", parsed);
+                parsed
+            } else {
+                debug!(log, "No captures");
+                return Ok(format!(
+                    "Invalid `{0}weather` syntax, try: `{0}help weather`",
+                    prefix
+                ));
+            };
+            let (max_hours, max_days) = provider.limits();
+            if range.end > max_hours && h || range.end > max_days && d {
+                return Ok(format!(
+                    "Weather data is only available for the next {}h or {}d.",
+                    max_hours, max_days
+                ));
+            }
+
+            (
+                range.clone(),
+                h,
+                d,
+                match location {
+                    Some(loc) => {
+                        // Comparisons don't change the user's location
+                        let compared = compared(loc);
+                        if compared.len() > 1 {
+                            return compare(
+                                cfg,
+                                log,
+                                &compared,
+                                (range.clone(), h, d),
+                                phrase,
+                                units,
+                                target,
+                            );
+                        }
+
+                        let new_loc = loc.to_owned();
+                        // Potentially update the cache and DB
+                        let mut cache = LOCATION_CACHE.write();
+                        if let Some(cached_loc) =
+                            cache.get(&(cfg.address.clone(), nick.to_owned())).cloned()
+                        {
+                            // Only update if the location actually changed
+                            if cached_loc.to_lowercase() != new_loc.to_lowercase() {
+                                trace!(log, "Updating Cache/DB");
+                                cache.remove(&(cfg.address.clone(), nick.to_owned()));
+                                cache.insert(
+                                    (cfg.address.clone(), nick.to_owned()),
+                                    new_loc.clone(),
+                                );
+                                drop(cache);
+
+                                super::with_database(cfg, |db| {
+                                    diesel::update(
+                                        lc_dsl::location_cache
+                                            .filter(lc_dsl::server.eq(&cfg.address))
+                                            .filter(lc_dsl::nick.eq(nick)),
+                                    ).set(lc_dsl::location.eq(new_loc.clone()))
+                                        .execute(db)?;
+                                    Ok(())
+                                })?;
+                            } else {
+                                trace!(log, "No location update needed")
+                            }
+                        } else {
+                            trace!(log, "Inserting into Cache/DB");
+                            cache.insert((cfg.address.clone(), nick.to_owned()), new_loc.clone());
+                            drop(cache);
+
+                            let new = models::NewLocation {
+                                server: &cfg.address,
+                                nick: nick,
+                                location: &*new_loc,
+                            };
+                            super::with_database(cfg, |db| {
+                                diesel::insert_into(schema::location_cache::table)
+                                    .values(&new)
+                                    .execute(db)?;
+                                Ok(())
+                            })?;
+                        }
+                        new_loc
+                    }
+                    None => {
+                        let cache = LOCATION_CACHE.read();
+                        if let Some(cached_loc) =
+                            cache.get(&(cfg.address.clone(), nick.to_owned())).cloned()
+                        {
+                            cached_loc
+                        } else {
+                            debug!(log, "No location found");
+                            return Ok(format!(
+                                "Invalid `{0}weather` syntax, try: `{0}help weather`",
+                                prefix
+                            ));
+                        }
+                    }
+                },
+            )
+        }
+    };
+
+    let client = Client::new();
+    let Fetched {
+        location: reverse_location,
+        latitude,
+        longitude,
+        forecast: res,
+        offset,
+        range,
+        hours,
+        days,
+    } = match fetch(
+        cfg,
+        &client,
+        log,
+        &*provider,
+        &location,
+        (range, hours, days),
+        phrase,
+    )? {
+        Ok(fetched) => fetched,
+        Err(reply) => return Ok(reply),
+    };
+
+    let format_alerts = |out: &mut String, alerts: &[Alert]| -> Result<()> {
+        let range_adjustment = if days {
+            Duration::days(range.start as _)
+        } else {
+            Duration::hours(range.start as _)
+        };
+        let adjusted_request_time = Utc::now() + range_adjustment;
+        let mut num = 0;
+        for (n, a) in alerts.iter().enumerate() {
+            if adjusted_request_time > Utc.timestamp(a.expires, 0) {
+                trace!(log, "Expired alert");
+            } else {
+                num += 1;
+                let regions = if a.regions.is_empty() {
+                    String::new()
+                } else {
+                    a.regions
+                        .iter()
+                        .take(6)
+                        .fold(" in [".to_owned(), |acc, reg| acc + reg + ", ") + "…]"
+                };
+                super::send_segmented_message(
+                    cfg,
+                    log,
+                    nick,
+                    None,
+                    "weather",
+                    &format!(
+                        "{}{}; <{}>",
+                        format::bold(format!("{}: {}", n + 1, a.title)),
+                        regions,
+                        a.description
+                    ),
+                    Priority::Bulk,
+                )?;
+            }
+        }
+        if num != 0 {
+            out.push_str(&format!("; PMed {} alert(s)", num));
+        }
+        Ok(())
+    };
+    let unavailable = || Ok("Sorry, no weather data is available for that time.".to_owned());
+    let extras = Extras {
+        fields: &fields,
+        latitude: latitude,
+        longitude: longitude,
+        offset: offset,
+    };
+    // When the n-th data point is
+    let now = Utc::now();
+    let time = |n: usize| {
+        if days {
+            now + Duration::days(n as _)
+        } else {
+            now + Duration::hours(n as _)
+        }
+    };
+
+    let mut formatted = String::new();
+    // Hourly forecasts have no current data, e.g. for `in 0 hours`, their first hour is used
+    if range.start == range.end.saturating_sub(1) && range.start == 0 {
+        if days {
+            if let Some(data) = res.daily.first() {
+                formatted.push_str(&format!("Today's weather in {} is ", reverse_location));
+                format_data_point(&mut formatted, data, days, units, &extras, now);
+            } else {
+                return unavailable();
+            }
+        } else if let Some(data) = res.currently.as_ref().or_else(|| res.hourly.first()) {
+            formatted.push_str(&format!("Current weather in {} is ", reverse_location));
+            format_data_point(&mut formatted, data, days, units, &extras, now);
+        } else {
+            return unavailable();
+        }
+    } else if range.start == range.end.saturating_sub(1) {
+        let data = if days {
+            res.daily.get(range.start)
+        } else {
+            res.hourly.get(range.start)
+        };
+        if let Some(data) = data {
+            formatted.push_str(&format!(
+                "Weather in {}{} in {} is ",
+                range.start,
+                if days { "d" } else { "h" },
+                reverse_location
+            ));
+            format_data_point(&mut formatted, data, days, units, &extras, time(range.start));
+        } else {
+            return unavailable();
+        }
+    } else {
+        let data;
+        if hours {
+            data = &res.hourly;
+            formatted.push_str(&format!(
+                "Weather in the next {}-{}h in {}: ",
+                range.start,
+                range.end - 1,
+                reverse_location
+            ));
+        } else {
+            data = &res.daily;
+            formatted.push_str(&format!(
+                "Weather in the next {}-{}d in {}: ",
+                range.start,
+                range.end - 1,
+                reverse_location
+            ));
+        }
+        if data.len() <= range.start {
+            return unavailable();
+        }
+        let last = (range.end - 1).min(data.len() - 1);
+        for (n, data) in data[range.start..last + 1].iter().enumerate() {
+            formatted.push_str(&format!("{} ", format::bold(format!("{}:", n + range.start))));
+            format_data_point(
+                &mut formatted,
+                data,
+                days,
+                units,
+                &extras,
+                time(n + range.start),
+            );
+            if n + range.start != last {
+                formatted.push_str("--- ");
+            }
+        }
+    }
+    format_alerts(&mut formatted, &res.alerts)?;
+
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_range_location() {
+        assert_eq!(parse_range("Berlin"), Some((0..1, false, false, Some("Berlin"))));
+        assert_eq!(
+            parse_range("3rd Street"),
+            Some((0..1, false, false, Some("3rd Street")))
+        );
+        assert_eq!(parse_range("d"), Some((0..1, false, false, Some("d"))));
+        assert_eq!(parse_range(""), None);
+    }
+
+    #[test]
+    fn parse_range_single() {
+        assert_eq!(parse_range("3d Berlin"), Some((3..4, false, true, Some("Berlin"))));
+        assert_eq!(parse_range("12h"), Some((12..13, true, false, None)));
+        assert_eq!(parse_range("0 h  Tokyo "), Some((0..1, true, false, Some("Tokyo"))));
+    }
+
+    #[test]
+    fn parse_range_ranges() {
+        assert_eq!(
+            parse_range("1-3d Tokyo, JP"),
+            Some((1..4, false, true, Some("Tokyo, JP")))
+        );
+        assert_eq!(parse_range("0-47h"), Some((0..48, true, false, None)));
+        assert_eq!(parse_range("2-2d"), Some((2..3, false, true, None)));
+        assert_eq!(parse_range("5-3d Berlin vs Tokyo"), None);
+    }
+
+    #[test]
+    fn parse_range_overflow() {
+        assert_eq!(parse_range("99999999999999999999999d Berlin"), None);
+        assert_eq!(parse_range("0-18446744073709551615h"), None);
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use reqwest::Client;
+use serde_json::Value;
+use slog::Logger;
+
+use errors::*;
+use super::{DataPoint, Forecast, Span, WeatherProvider};
+
+pub const API_BASE: &str = "https://api.open-meteo.com";
+
+const FIELDS: &str = "weather_code,temperature_2m,apparent_temperature,cloud_cover,\
+                      relative_humidity_2m,precipitation_probability,precipitation,\
+                      wind_speed_10m,wind_direction_10m,pressure_msl,uv_index,\
+                      visibility,dew_point_2m";
+const DAILY_FIELDS: &str = "weather_code,temperature_2m_min,temperature_2m_max,\
+                            apparent_temperature_min,apparent_temperature_max,\
+                            precipitation_probability_max,precipitation_sum,\
+                            wind_speed_10m_max,wind_direction_10m_dominant,uv_index_max";
+
+pub struct OpenMeteo {
+    pub base: String,
+}
+
+impl WeatherProvider for OpenMeteo {
+    fn limits(&self) -> (usize, usize) {
+        (240, 15)
+    }
+
+    fn forecast(
+        &self,
+        client: &Client,
+        log: &Logger,
+        latitude: f64,
+        longitude: f64,
+        span: Span,
+    ) -> Result<Forecast> {
+        let (latitude, longitude) = (latitude.to_string(), longitude.to_string());
+        let mut query = vec![
+            ("latitude", latitude),
+            ("longitude", longitude),
+            ("timezone", "auto".to_owned()),
+        ];
+        // Hourly data starts with the current hour, daily data with today
+        match span {
+            Span::Current => query.push(("current", FIELDS.to_owned())),
+            Span::Hours(n) => {
+                query.push(("hourly", FIELDS.to_owned()));
+                query.push(("forecast_hours", n.to_string()));
+            }
+            Span::Days(n) => {
+                query.push(("daily", DAILY_FIELDS.to_owned()));
+                query.push(("forecast_days", n.to_string()));
+            }
+        }
+        let json: Value = client
+            .get(&format!("{}/v1/forecast", self.base))
+            .query(&query)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        trace!(log, "Open-Meteo forecast: {:?}", json);
+
+        Ok(Forecast {
+            timezone: json.get("timezone")
+                .and_then(Value::as_str)
+                .and_then(|tz| tz.parse().ok()),
+            currently: json.get("current")
+                .map(|current| data_point(|field| current.get(field))),
+            hourly: block(&json, "hourly", false),
+            daily: block(&json, "daily", true),
+            alerts: Vec::new(),
+        })
+    }
+}
+
+// Blocks are objects of arrays, with one value per hour or day
+fn block(json: &Value, name: &str, days: bool) -> Vec<DataPoint> {
+    let block = if let Some(block) = json.get(name) {
+        block
+    } else {
+        return Vec::new();
+    };
+    let len = block
+        .get("time")
+        .and_then(Value::as_array)
+        .map_or(0, Vec::len);
+    (0..len)
+        .map(|i| {
+            let column = |field: &str| block.get(field).and_then(|f| f.get(i));
+            if days {
+                let number = |field: &str| column(field).and_then(Value::as_f64);
+                let mut dp = data_point(&column);
+                dp.temperature_min = number("temperature_2m_min");
+                dp.temperature_max = number("temperature_2m_max");
+                dp.apparent_temperature_min = number("apparent_temperature_min");
+                dp.apparent_temperature_max = number("apparent_temperature_max");
+                dp.precip_probability =
+                    number("precipitation_probability_max").map(|p| p / 100.0);
+                dp.precip_amount = number("precipitation_sum");
+                dp.wind_speed = number("wind_speed_10m_max");
+                dp.wind_bearing = number("wind_direction_10m_dominant");
+                dp.uv_index = number("uv_index_max");
+                dp
+            } else {
+                data_point(&column)
+            }
+        })
+        .collect()
+}
+
+fn data_point<'a, F: Fn(&str) -> Option<&'a Value>>(field: F) -> DataPoint {
+    let number = |name: &str| field(name).and_then(Value::as_f64);
+    let percent = |name: &str| number(name).map(|p| p / 100.0);
+    let (summary, precip_type) = field("weather_code")
+        .and_then(Value::as_u64)
+        .map_or((None, None), describe);
+    DataPoint {
+        summary: summary.map(str::to_owned),
+        temperature: number("temperature_2m"),
+        apparent_temperature: number("apparent_temperature"),
+        cloud_cover: percent("cloud_cover"),
+        humidity: percent("relative_humidity_2m"),
+        precip_probability: percent("precipitation_probability"),
+        precip_type: precip_type.map(str::to_owned),
+        precip_amount: number("precipitation"),
+        wind_speed: number("wind_speed_10m"),
+        wind_bearing: number("wind_direction_10m"),
+        pressure: number("pressure_msl"),
+        uv_index: number("uv_index"),
+        // m
+        visibility: number("visibility").map(|v| v / 1000.0),
+        dew_point: number("dew_point_2m"),
+        ..Default::default()
+    }
+}
+
+// WMO weather interpretation codes, as summary and type of precipitation
+fn describe(code: u64) -> (Option<&'static str>, Option<&'static str>) {
+    let (summary, precip_type) = match code {
+        0 => ("Clear sky", None),
+        1 => ("Mainly clear", None),
+        2 => ("Partly cloudy", None),
+        3 => ("Overcast", None),
+        45 | 48 => ("Fog", None),
+        51 | 53 | 55 => ("Drizzle", Some("drizzle")),
+        56 | 57 => ("Freezing drizzle", Some("freezing drizzle")),
+        61 => ("Light rain", Some("rain")),
+        63 => ("Rain", Some("rain")),
+        65 => ("Heavy rain", Some("rain")),
+        66 | 67 => ("Freezing rain", Some("freezing rain")),
+        71 => ("Light snow", Some("snow")),
+        73 => ("Snow", Some("snow")),
+        75 => ("Heavy snow", Some("snow")),
+        77 => ("Snow grains", Some("snow")),
+        80 | 81 => ("Rain showers", Some("rain")),
+        82 => ("Violent rain showers", Some("rain")),
+        85 | 86 => ("Snow showers", Some("snow")),
+        95 => ("Thunderstorm", Some("rain")),
+        96 | 99 => ("Thunderstorm with hail", Some("hail")),
+        _ => return (None, None),
+    };
+    (Some(summary), precip_type)
+}
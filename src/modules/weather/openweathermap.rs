@@ -0,0 +1,150 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use reqwest::Client;
+use serde_json::Value;
+use slog::Logger;
+
+use std::f64;
+
+use errors::*;
+use super::{number, Alert, DataPoint, Forecast, Span, WeatherProvider};
+
+pub const API_BASE: &str = "https://api.openweathermap.org";
+
+pub struct OpenWeatherMap {
+    pub base: String,
+    pub key: String,
+}
+
+impl WeatherProvider for OpenWeatherMap {
+    fn limits(&self) -> (usize, usize) {
+        (47, 7)
+    }
+
+    fn forecast(
+        &self,
+        client: &Client,
+        log: &Logger,
+        latitude: f64,
+        longitude: f64,
+        span: Span,
+    ) -> Result<Forecast> {
+        let exclude = if span == Span::Current {
+            "minutely,hourly,daily"
+        } else {
+            "minutely,current"
+        };
+        let json: Value = client
+            .get(&format!("{}/data/3.0/onecall", self.base))
+            .query(&[
+                ("lat", latitude.to_string()),
+                ("lon", longitude.to_string()),
+                ("units", "metric".to_owned()),
+                ("exclude", exclude.to_owned()),
+                ("appid", self.key.clone()),
+            ])
+            .send()?
+            .error_for_status()?
+            .json()?;
+        trace!(log, "OpenWeatherMap forecast: {:?}", json);
+
+        let block = |name: &str| {
+            json.get(name)
+                .and_then(Value::as_array)
+                .map(|data| data.iter().map(data_point).collect())
+                .unwrap_or_default()
+        };
+        Ok(Forecast {
+            timezone: json.get("timezone")
+                .and_then(Value::as_str)
+                .and_then(|tz| tz.parse().ok()),
+            currently: json.get("current").map(data_point),
+            hourly: block("hourly"),
+            daily: block("daily"),
+            alerts: json.get("alerts")
+                .and_then(Value::as_array)
+                .map(|alerts| alerts.iter().filter_map(alert).collect())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+fn data_point(json: &Value) -> DataPoint {
+    let percent = |field: &str| number(json, field).map(|p| p / 100.0);
+    // mm, of the last hour as `{"1h": n}`, or of days as number
+    let amount = |field: &str| {
+        json.get(field)
+            .and_then(|a| a.as_f64().or_else(|| number(a, "1h")))
+    };
+    let mut dp = DataPoint {
+        summary: json.pointer("/weather/0/description")
+            .and_then(Value::as_str)
+            .map(str::to_owned),
+        cloud_cover: percent("clouds"),
+        humidity: percent("humidity"),
+        precip_probability: number(json, "pop"),
+        precip_type: if json.get("snow").is_some() {
+            Some("snow".to_owned())
+        } else if json.get("rain").is_some() {
+            Some("rain".to_owned())
+        } else {
+            None
+        },
+        precip_amount: match (amount("rain"), amount("snow")) {
+            (None, None) => None,
+            (rain, snow) => Some(rain.unwrap_or(0.0) + snow.unwrap_or(0.0)),
+        },
+        // m/s
+        wind_speed: number(json, "wind_speed").map(|s| s * 3.6),
+        wind_bearing: number(json, "wind_deg"),
+        pressure: number(json, "pressure"),
+        uv_index: number(json, "uvi"),
+        // m
+        visibility: number(json, "visibility").map(|v| v / 1000.0),
+        dew_point: number(json, "dew_point"),
+        ..Default::default()
+    };
+    // Days have the temperatures of their morning, day, evening and night
+    if let Some(temp) = json.get("temp").filter(|t| t.is_object()) {
+        dp.temperature_min = number(temp, "min");
+        dp.temperature_max = number(temp, "max");
+        let feels_like = json.get("feels_like")
+            .and_then(Value::as_object)
+            .map(|f| f.values().filter_map(Value::as_f64).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if !feels_like.is_empty() {
+            dp.apparent_temperature_min =
+                Some(feels_like.iter().cloned().fold(f64::MAX, f64::min));
+            dp.apparent_temperature_max =
+                Some(feels_like.iter().cloned().fold(f64::MIN, f64::max));
+        }
+    } else {
+        dp.temperature = number(json, "temp");
+        dp.apparent_temperature = number(json, "feels_like");
+    }
+    dp
+}
+
+fn alert(json: &Value) -> Option<Alert> {
+    Some(Alert {
+        title: json.get("event")?.as_str()?.to_owned(),
+        regions: Vec::new(),
+        description: json.get("description")?.as_str()?.to_owned(),
+        expires: json.get("end")?.as_i64()?,
+    })
+}
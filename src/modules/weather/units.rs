@@ -0,0 +1,135 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use config::Units;
+
+// Data points are in °C, km/h, mm, km and hPa
+impl Units {
+    pub fn temperature(self, celsius: f64) -> f64 {
+        match self {
+            Units::Imperial => round(celsius * 9.0 / 5.0 + 32.0, 1),
+            Units::Metric | Units::Uk | Units::Si => round(celsius, 1),
+        }
+    }
+
+    pub fn temperature_label(self) -> &'static str {
+        match self {
+            Units::Imperial => "°F",
+            Units::Metric | Units::Uk | Units::Si => "°C",
+        }
+    }
+
+    pub fn speed(self, kmh: f64) -> String {
+        match self {
+            Units::Metric => format!("{}km/h", round(kmh, 1)),
+            Units::Imperial | Units::Uk => format!("{}mph", round(kmh / 1.609_344, 1)),
+            Units::Si => format!("{}m/s", round(kmh / 3.6, 1)),
+        }
+    }
+
+    pub fn precipitation(self, mm: f64) -> String {
+        match self {
+            Units::Imperial => format!("{}in", round(mm / 25.4, 2)),
+            Units::Metric | Units::Uk | Units::Si => format!("{}mm", round(mm, 1)),
+        }
+    }
+
+    pub fn distance(self, km: f64) -> String {
+        match self {
+            Units::Imperial | Units::Uk => format!("{}mi", round(km / 1.609_344, 1)),
+            Units::Metric | Units::Si => format!("{}km", round(km, 1)),
+        }
+    }
+
+    pub fn pressure(self, hpa: f64) -> String {
+        match self {
+            Units::Imperial => format!("{}inHg", round(hpa * 0.029_53, 2)),
+            Units::Metric | Units::Uk | Units::Si => format!("{}hPa", hpa.round()),
+        }
+    }
+}
+
+// Rounds to `decimals` decimal places
+fn round(n: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (n * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Units::parse("Imperial"), Some(Units::Imperial));
+        assert_eq!(Units::parse("si"), Some(Units::Si));
+        assert_eq!(Units::parse("kelvin"), None);
+        for &u in &[Units::Metric, Units::Imperial, Units::Uk, Units::Si] {
+            assert_eq!(Units::parse(u.name()), Some(u));
+        }
+    }
+
+    #[test]
+    fn temperature() {
+        assert_eq!(Units::Metric.temperature(21.44), 21.4);
+        assert_eq!(Units::Uk.temperature(-3.06), -3.1);
+        assert_eq!(Units::Imperial.temperature(0.0), 32.0);
+        assert_eq!(Units::Imperial.temperature(100.0), 212.0);
+        assert_eq!(Units::Imperial.temperature(-40.0), -40.0);
+        assert_eq!(Units::Imperial.temperature(21.5), 70.7);
+        assert_eq!(Units::Si.temperature_label(), "°C");
+        assert_eq!(Units::Imperial.temperature_label(), "°F");
+    }
+
+    #[test]
+    fn speed() {
+        assert_eq!(Units::Metric.speed(10.0), "10km/h");
+        assert_eq!(Units::Imperial.speed(16.093_44), "10mph");
+        assert_eq!(Units::Uk.speed(100.0), "62.1mph");
+        assert_eq!(Units::Si.speed(36.0), "10m/s");
+        assert_eq!(Units::Si.speed(0.0), "0m/s");
+    }
+
+    #[test]
+    fn precipitation() {
+        assert_eq!(Units::Metric.precipitation(1.24), "1.2mm");
+        assert_eq!(Units::Imperial.precipitation(25.4), "1in");
+        assert_eq!(Units::Imperial.precipitation(12.7), "0.5in");
+        assert_eq!(Units::Imperial.precipitation(0.1), "0in");
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(Units::Uk.distance(1.609_344), "1mi");
+        assert_eq!(Units::Imperial.distance(20.0), "12.4mi");
+        assert_eq!(Units::Si.distance(10.0), "10km");
+    }
+
+    #[test]
+    fn pressure() {
+        assert_eq!(Units::Metric.pressure(1013.25), "1013hPa");
+        assert_eq!(Units::Imperial.pressure(1013.25), "29.92inHg");
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(round(1.25, 1), 1.3);
+        assert_eq!(round(-1.25, 1), -1.3);
+        assert_eq!(round(1.234_5, 2), 1.23);
+        assert_eq!(round(1234.5, 0), 1235.0);
+    }
+}
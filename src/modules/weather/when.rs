@@ -0,0 +1,242 @@
+// Copyright (C) 2017  ParadoxSpiral
+//
+// This file is part of parabot.
+//
+// Parabot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Parabot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
+
+use chrono::prelude::*;
+
+use std::ops::Range;
+
+// Only full names may stand alone, `sun` or `sat` are more likely part of a location
+const WEEKDAYS: &[(&str, Weekday)] = &[
+    ("monday", Weekday::Mon),
+    ("tuesday", Weekday::Tue),
+    ("wednesday", Weekday::Wed),
+    ("thursday", Weekday::Thu),
+    ("friday", Weekday::Fri),
+    ("saturday", Weekday::Sat),
+    ("sunday", Weekday::Sun),
+];
+
+/// A time asked for in words, like `tomorrow` or `in 3 hours`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phrase {
+    Now,
+    Today,
+    Tonight,
+    Tomorrow,
+    Weekend,
+    Weekday(Weekday),
+    InHours(usize),
+    InDays(usize),
+    NextHours(usize),
+    NextDays(usize),
+}
+
+/// Parses a phrase at the start of `words`, returns it and how many words it spans
+pub fn parse(words: &[&str]) -> Option<(Phrase, usize)> {
+    let lower = words
+        .iter()
+        .take(3)
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>();
+    let word = |i: usize| lower.get(i).map(|w| &**w);
+    // Also short forms, e.g. `mon`
+    let weekday = |w: Option<&str>| w.and_then(|w| w.parse::<Weekday>().ok());
+    let full_weekday = |w: Option<&str>| {
+        WEEKDAYS
+            .iter()
+            .find(|&&(name, _)| Some(name) == w)
+            .map(|&(_, day)| day)
+    };
+
+    match (word(0), word(1)) {
+        (Some("now"), _) => Some((Phrase::Now, 1)),
+        (Some("today"), _) => Some((Phrase::Today, 1)),
+        (Some("tonight"), _) => Some((Phrase::Tonight, 1)),
+        (Some("tomorrow"), _) => Some((Phrase::Tomorrow, 1)),
+        (Some("weekend"), _) => Some((Phrase::Weekend, 1)),
+        (Some("this"), Some("weekend")) | (Some("next"), Some("weekend")) => {
+            Some((Phrase::Weekend, 2))
+        }
+        (Some("on"), w) | (Some("this"), w) | (Some("next"), w) if weekday(w).is_some() => {
+            Some((Phrase::Weekday(weekday(w).unwrap()), 2))
+        }
+        (w, _) if full_weekday(w).is_some() => {
+            Some((Phrase::Weekday(full_weekday(w).unwrap()), 1))
+        }
+        // `in 3 hours`, `next 2 days`, also `in 3h`
+        (Some(first @ "in"), Some(n)) | (Some(first @ "next"), Some(n)) => {
+            let (n, unit, len) = match n.find(|c: char| !c.is_ascii_digit()) {
+                Some(i) if i != 0 => (&n[..i], &n[i..], 2),
+                None => (n, word(2)?, 3),
+                _ => return None,
+            };
+            let n = n.parse::<usize>().ok()?;
+            let phrase = match (first, unit) {
+                ("in", "h") | ("in", "hour") | ("in", "hours") => Phrase::InHours(n),
+                ("in", "d") | ("in", "day") | ("in", "days") => Phrase::InDays(n),
+                ("next", "h") | ("next", "hour") | ("next", "hours") if n != 0 => {
+                    Phrase::NextHours(n)
+                }
+                ("next", "d") | ("next", "day") | ("next", "days") if n != 0 => {
+                    Phrase::NextDays(n)
+                }
+                _ => return None,
+            };
+            Some((phrase, len))
+        }
+        _ => None,
+    }
+}
+
+/// The range of hours or days from `now` at the location the phrase means, and whether they are
+/// hours or days; neither is the current weather
+pub fn resolve(phrase: Phrase, now: DateTime<FixedOffset>) -> (Range<usize>, bool, bool) {
+    let hour = now.hour() as usize;
+    let weekday = now.weekday().num_days_from_monday() as usize;
+    let (hours, days) = (|r: Range<usize>| (r, true, false), |r: Range<usize>| (r, false, true));
+    match phrase {
+        Phrase::Now => (0..1, false, false),
+        Phrase::Today => days(0..1),
+        Phrase::Tomorrow => days(1..2),
+        // From 18:00, or now, until 06:00
+        Phrase::Tonight => if hour < 6 {
+            hours(0..6 - hour)
+        } else {
+            hours(18usize.saturating_sub(hour)..30 - hour)
+        },
+        Phrase::Weekend => match weekday {
+            5 => days(0..2),
+            6 => days(0..1),
+            _ => days(5 - weekday..7 - weekday),
+        },
+        Phrase::Weekday(w) => {
+            let d = (w.num_days_from_monday() as usize + 7 - weekday) % 7;
+            days(d..d + 1)
+        }
+        Phrase::InHours(n) => hours(n..n + 1),
+        Phrase::InDays(n) => days(n..n + 1),
+        Phrase::NextHours(n) => hours(0..n),
+        Phrase::NextDays(n) => days(0..n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wednesday, 2026-10-14
+    fn at(day: u32, hour: u32) -> DateTime<FixedOffset> {
+        FixedOffset::east(0).ymd(2026, 10, day).and_hms(hour, 0, 0)
+    }
+
+    #[test]
+    fn parse_words() {
+        assert_eq!(parse(&["tomorrow", "Tokyo"]), Some((Phrase::Tomorrow, 1)));
+        assert_eq!(parse(&["NOW"]), Some((Phrase::Now, 1)));
+        assert_eq!(parse(&["tonight"]), Some((Phrase::Tonight, 1)));
+        assert_eq!(parse(&["this", "weekend"]), Some((Phrase::Weekend, 2)));
+        assert_eq!(parse(&["Berlin"]), None);
+        assert_eq!(parse(&[]), None);
+    }
+
+    #[test]
+    fn parse_weekdays() {
+        assert_eq!(
+            parse(&["Monday", "Paris"]),
+            Some((Phrase::Weekday(Weekday::Mon), 1))
+        );
+        assert_eq!(
+            parse(&["on", "sun"]),
+            Some((Phrase::Weekday(Weekday::Sun), 2))
+        );
+        assert_eq!(
+            parse(&["next", "friday"]),
+            Some((Phrase::Weekday(Weekday::Fri), 2))
+        );
+        // Short forms only after `on`, `this` or `next`
+        assert_eq!(parse(&["sun", "Berlin"]), None);
+        assert_eq!(parse(&["Sun", "City"]), None);
+        assert_eq!(parse(&["sat"]), None);
+    }
+
+    #[test]
+    fn parse_numbers() {
+        assert_eq!(parse(&["in", "3", "hours"]), Some((Phrase::InHours(3), 3)));
+        assert_eq!(parse(&["in", "3h", "Rome"]), Some((Phrase::InHours(3), 2)));
+        assert_eq!(
+            parse(&["in", "2", "days", "Paris"]),
+            Some((Phrase::InDays(2), 3))
+        );
+        assert_eq!(parse(&["next", "12", "hours"]), Some((Phrase::NextHours(12), 3)));
+        assert_eq!(parse(&["next", "3d"]), Some((Phrase::NextDays(3), 2)));
+        assert_eq!(parse(&["next", "0", "hours"]), None);
+        assert_eq!(parse(&["in", "3"]), None);
+        assert_eq!(parse(&["in", "3", "weeks"]), None);
+        assert_eq!(parse(&["in", "x", "hours"]), None);
+        assert_eq!(parse(&["in", "Berlin"]), None);
+        assert_eq!(parse(&["in", "99999999999999999999999", "hours"]), None);
+    }
+
+    #[test]
+    fn resolve_days() {
+        assert_eq!(resolve(Phrase::Now, at(14, 14)), (0..1, false, false));
+        assert_eq!(resolve(Phrase::Today, at(14, 14)), (0..1, false, true));
+        assert_eq!(resolve(Phrase::Tomorrow, at(14, 23)), (1..2, false, true));
+        assert_eq!(resolve(Phrase::InDays(3), at(14, 0)), (3..4, false, true));
+        assert_eq!(resolve(Phrase::NextDays(3), at(14, 0)), (0..3, false, true));
+    }
+
+    #[test]
+    fn resolve_tonight() {
+        // From 18:00 to 06:00
+        assert_eq!(resolve(Phrase::Tonight, at(14, 14)), (4..16, true, false));
+        assert_eq!(resolve(Phrase::Tonight, at(14, 20)), (0..10, true, false));
+        assert_eq!(resolve(Phrase::Tonight, at(14, 3)), (0..3, true, false));
+        assert_eq!(resolve(Phrase::Tonight, at(14, 5)), (0..1, true, false));
+    }
+
+    #[test]
+    fn resolve_weekdays() {
+        assert_eq!(resolve(Phrase::Weekend, at(14, 12)), (3..5, false, true));
+        assert_eq!(resolve(Phrase::Weekend, at(17, 12)), (0..2, false, true));
+        assert_eq!(resolve(Phrase::Weekend, at(18, 12)), (0..1, false, true));
+        assert_eq!(resolve(Phrase::Weekend, at(19, 12)), (5..7, false, true));
+        assert_eq!(
+            resolve(Phrase::Weekday(Weekday::Mon), at(14, 12)),
+            (5..6, false, true)
+        );
+        assert_eq!(
+            resolve(Phrase::Weekday(Weekday::Wed), at(14, 12)),
+            (0..1, false, true)
+        );
+    }
+
+    #[test]
+    fn resolve_hours() {
+        assert_eq!(resolve(Phrase::InHours(3), at(14, 12)), (3..4, true, false));
+        assert_eq!(resolve(Phrase::InHours(0), at(14, 12)), (0..1, true, false));
+        assert_eq!(resolve(Phrase::NextHours(6), at(14, 12)), (0..6, true, false));
+    }
+
+    #[test]
+    fn resolve_local_time() {
+        // 14:00 UTC is 23:00 in Tokyo
+        let tokyo = at(14, 14).with_timezone(&FixedOffset::east(9 * 3600));
+        assert_eq!(resolve(Phrase::Tonight, tokyo), (0..7, true, false));
+        assert_eq!(resolve(Phrase::Today, tokyo), (0..1, false, true));
+    }
+}
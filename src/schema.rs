@@ -15,12 +15,40 @@
 // You should have received a copy of the GNU General Public License
 // along with Parabot.  If not, see <http://www.gnu.org/licenses/>.
 
+table! {
+    command_aliases (server, scope, name) {
+        server -> Text,
+        scope -> Text,
+        name -> Text,
+        expansion -> Text,
+    }
+}
+
 table! {
     geocode_cache (location) {
         location -> Text,
         latitude -> Float,
         longitude -> Float,
         reverse_location -> Text,
+        provider -> Text,
+    }
+}
+
+table! {
+    geoname_names (name, geoname_id) {
+        name -> Text,
+        geoname_id -> Integer,
+    }
+}
+
+table! {
+    geonames (id) {
+        id -> Integer,
+        name -> Text,
+        country_code -> Text,
+        latitude -> Double,
+        longitude -> Double,
+        population -> BigInt,
     }
 }
 
@@ -33,18 +61,35 @@ table! {
 }
 
 table! {
-    pending_tells (date, message) {
-        date -> Text,
+    pending_tells (id) {
+        id -> Integer,
+        date -> Timestamp,
         server_addr -> Text,
         channel -> Nullable<Text>,
         source_nick -> Text,
         target_nick -> Text,
         message -> Text,
+        snoozed_until -> Nullable<Timestamp>,
+        expires_at -> Nullable<Timestamp>,
+        source_network -> Nullable<Text>,
+    }
+}
+
+table! {
+    user_preferences (server, nick, name) {
+        server -> Text,
+        nick -> Text,
+        name -> Text,
+        value -> Text,
     }
 }
 
 allow_tables_to_appear_in_same_query!(
+    command_aliases,
     geocode_cache,
+    geoname_names,
+    geonames,
     location_cache,
     pending_tells,
+    user_preferences,
 );